@@ -0,0 +1,311 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::best_response::victory_points;
+use crate::core::{self, BattleScore, N_CASTLES};
+use crate::fixed_point::Precision;
+
+/// How many points of the score trajectory are kept for each run
+const TRAJECTORY_POINTS: usize = 100;
+
+/// How many steps the adaptive schedule measures the acceptance rate over before adjusting
+/// the temperature
+const ADAPTIVE_WINDOW: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Objective {
+    Wins,
+    /// 1 point for a win and half a point for a tie
+    VictoryPoints,
+}
+
+impl Objective {
+    fn value(self, score: &BattleScore) -> f64 {
+        match self {
+            Objective::Wins => score.wins as f64,
+            Objective::VictoryPoints => victory_points(score) as f64 / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Schedule {
+    /// Multiply the temperature by the same factor every step, so that it goes from the
+    /// initial to the final temperature over the run
+    Geometric,
+    /// Every `ADAPTIVE_WINDOW` steps, nudge the temperature so that the fraction of moves
+    /// accepted tracks a target, which falls from `target_acceptance` to 0 over the run
+    Adaptive { target_acceptance: f64 },
+    /// Geometric cooling, but go back to the initial temperature after `patience` steps
+    /// without a new best
+    Reheating { patience: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AnnealingConfig {
+    pub objective: Objective,
+    pub schedule: Schedule,
+    pub initial_temperature: f64,
+    pub final_temperature: f64,
+    /// How many moves each run tries
+    pub n_steps: usize,
+    /// Each move transfers between 1 and this many troops from one castle to another
+    pub max_transfer: i16,
+    /// What the allocations are counted in. `max_transfer` is in these units too
+    pub precision: Precision,
+    /// How many runs to do in parallel, each from a uniform random start
+    pub n_restarts: usize,
+    /// Run `i` is seeded with `seed + i`
+    pub seed: u64,
+}
+
+impl Default for AnnealingConfig {
+    fn default() -> Self {
+        AnnealingConfig {
+            objective: Objective::Wins,
+            schedule: Schedule::Geometric,
+            initial_temperature: 5.0,
+            final_temperature: 0.05,
+            n_steps: 20_000,
+            max_transfer: 5,
+            precision: Precision::WHOLE,
+            n_restarts: 4,
+            seed: 0,
+        }
+    }
+}
+
+impl AnnealingConfig {
+    /// validate checks that the temperatures are finite and above 0, that the final
+    /// temperature is no higher than the initial one, and that moves transfer something
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_transfer < 1 {
+            return Err(format!(
+                "The most troops a move transfers must be above 0, got {} units",
+                self.max_transfer
+            ));
+        }
+        let (initial, final_) = (self.initial_temperature, self.final_temperature);
+        if !(initial.is_finite() && initial > 0.0 && final_.is_finite() && final_ > 0.0) {
+            return Err(format!(
+                "The temperatures must be finite and above 0, got {initial} and {final_}"
+            ));
+        }
+        if final_ > initial {
+            return Err(format!(
+                "The final temperature {final_} must not be above the initial temperature {initial}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A snapshot of a run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TrajectoryPoint {
+    pub step: usize,
+    pub temperature: f64,
+    pub current: f64,
+    pub best: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnealingResult {
+    pub best: [i16; N_CASTLES],
+    pub best_score: BattleScore,
+    /// The objective of the best allocation
+    pub best_value: f64,
+    /// About `TRAJECTORY_POINTS` snapshots of the run that found the best allocation
+    pub trajectory: Vec<TrajectoryPoint>,
+}
+
+/// random_transfer moves between 1 and `max_transfer` troops from one random castle to
+/// another, so the total number of troops stays the same
+fn random_transfer<R: Rng>(
+    mut player: [i16; N_CASTLES],
+    max_transfer: i16,
+    rng: &mut R,
+) -> [i16; N_CASTLES] {
+    let from = loop {
+        let from = rng.gen_range(0..N_CASTLES);
+        if player[from] > 0 {
+            break from;
+        }
+    };
+    let to = (from + rng.gen_range(1..N_CASTLES)) % N_CASTLES;
+    let n = rng.gen_range(1..=player[from].min(max_transfer));
+    player[from] -= n;
+    player[to] += n;
+    player
+}
+
+fn anneal_once<R: Rng>(
+    field: &[[i16; N_CASTLES]],
+    config: &AnnealingConfig,
+    rng: &mut R,
+) -> AnnealingResult {
+    let n_steps = config.n_steps.max(1);
+    let cooling =
+        (config.final_temperature / config.initial_temperature).powf(1.0 / n_steps as f64);
+    let record_every = (n_steps / TRAJECTORY_POINTS).max(1);
+
+    let mut current = config.precision.generate_uniform_random_distribution(rng);
+    let mut current_score = core::score_against_field(current, field);
+    let mut current_value = config.objective.value(&current_score);
+    let (mut best, mut best_score, mut best_value) = (current, current_score, current_value);
+    let mut temperature = config.initial_temperature;
+    let mut trajectory = Vec::with_capacity(TRAJECTORY_POINTS + 1);
+    let mut steps_since_best = 0;
+    let mut accepted_in_window = 0;
+
+    for step in 0..n_steps {
+        if step % record_every == 0 {
+            trajectory.push(TrajectoryPoint {
+                step,
+                temperature,
+                current: current_value,
+                best: best_value,
+            });
+        }
+
+        let candidate = random_transfer(current, config.max_transfer, rng);
+        let score = core::score_against_field(candidate, field);
+        let value = config.objective.value(&score);
+        let delta = value - current_value;
+        if delta >= 0.0 || rng.gen::<f64>() < (delta / temperature).exp() {
+            (current, current_score, current_value) = (candidate, score, value);
+            accepted_in_window += 1;
+        }
+        if current_value > best_value {
+            (best, best_score, best_value) = (current, current_score, current_value);
+            steps_since_best = 0;
+        } else {
+            steps_since_best += 1;
+        }
+
+        match config.schedule {
+            Schedule::Geometric => temperature *= cooling,
+            Schedule::Adaptive { target_acceptance } => {
+                if (step + 1) % ADAPTIVE_WINDOW == 0 {
+                    let target = target_acceptance * (1.0 - step as f64 / n_steps as f64);
+                    let rate = accepted_in_window as f64 / ADAPTIVE_WINDOW as f64;
+                    temperature *= if rate > target { 0.9 } else { 1.1 };
+                    temperature =
+                        temperature.clamp(config.final_temperature, config.initial_temperature);
+                    accepted_in_window = 0;
+                }
+            }
+            Schedule::Reheating { patience } => {
+                temperature *= cooling;
+                if steps_since_best >= patience {
+                    temperature = config.initial_temperature;
+                    steps_since_best = 0;
+                }
+            }
+        }
+    }
+    trajectory.push(TrajectoryPoint {
+        step: n_steps,
+        temperature,
+        current: current_value,
+        best: best_value,
+    });
+
+    AnnealingResult {
+        best,
+        best_score,
+        best_value,
+        trajectory,
+    }
+}
+
+/// anneal runs `config.n_restarts` simulated annealing runs against `field` in parallel,
+/// and returns the best allocation found, with the trajectory of the run that found it.
+/// Panics if `config` is not valid.
+pub fn anneal(field: &[[i16; N_CASTLES]], config: &AnnealingConfig) -> AnnealingResult {
+    if let Err(e) = config.validate() {
+        panic!("{e}");
+    }
+    let _span = tracing::info_span!("anneal", n_restarts = config.n_restarts).entered();
+    (0..config.n_restarts.max(1))
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+            anneal_once(field, config, &mut rng)
+        })
+        .max_by(|a, b| a.best_value.total_cmp(&b.best_value))
+        .expect("There is always at least one restart")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_transfer_keeps_troops() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut p: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        for _ in 0..1000 {
+            let moved = random_transfer(p, 5, &mut rng);
+            assert!(core::is_valid_distribution(&moved));
+            assert_ne!(p, moved);
+            p = moved;
+        }
+    }
+
+    #[test]
+    fn test_anneal() {
+        let field: Vec<[i16; 10]> = vec![
+            [10, 10, 10, 10, 10, 10, 10, 10, 10, 10],
+            [0, 0, 0, 0, 0, 20, 20, 20, 20, 20],
+            [0, 0, 0, 0, 0, 0, 0, 30, 30, 40],
+        ];
+        for schedule in [
+            Schedule::Geometric,
+            Schedule::Adaptive {
+                target_acceptance: 0.5,
+            },
+            Schedule::Reheating { patience: 500 },
+        ] {
+            let config = AnnealingConfig {
+                schedule,
+                n_steps: 2000,
+                seed: 1,
+                ..Default::default()
+            };
+            let res = anneal(&field, &config);
+            assert!(core::is_valid_distribution(&res.best));
+            assert_eq!(3, res.best_score.wins);
+            assert_eq!(res.best_score, core::score_against_field(res.best, &field));
+
+            // The best score never goes down
+            assert!(res.trajectory.windows(2).all(|w| w[0].best <= w[1].best));
+            assert_eq!(3.0, res.trajectory.last().unwrap().best);
+        }
+
+        // Runs with the same seed are the same
+        let config = AnnealingConfig {
+            n_steps: 500,
+            ..Default::default()
+        };
+        assert_eq!(anneal(&field, &config), anneal(&field, &config));
+
+        // Temperatures that would panic or give a NaN cooling factor are rejected
+        for (initial, final_) in [(1.0, 5.0), (0.0, 0.0), (f64::NAN, 1.0), (5.0, -1.0)] {
+            let config = AnnealingConfig {
+                initial_temperature: initial,
+                final_temperature: final_,
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+        }
+        let config = AnnealingConfig {
+            max_transfer: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(AnnealingConfig::default().validate().is_ok());
+    }
+}
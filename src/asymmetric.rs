@@ -0,0 +1,408 @@
+//! Asymmetric games, where the two sides can have different troop budgets, and can value
+//! the castles differently. Each side is scored by its own values, so a battle can look
+//! like a win to both sides, and the sides are played as two separate populations, A
+//! against B.
+
+use std::cmp::Ordering;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::best_response::victory_points;
+use crate::core::{self, BattleScore, N_CASTLES, N_TROOPS};
+use crate::equilibrium::{self, Equilibrium};
+use crate::local_search::{self, LocalSearchConfig};
+use crate::metrics;
+
+/// How close, as a fraction of a side's total value, two point totals have to be to count
+/// as a tie
+const TIE_TOLERANCE: f32 = 1e-5;
+
+/// One of the two sides of an asymmetric game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Player {
+    A,
+    B,
+}
+
+/// What one side has to spend, and what the castles are worth to it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SideRules {
+    pub budget: i16,
+    pub values: [f32; N_CASTLES],
+}
+
+impl Default for SideRules {
+    /// The rules of the original game: `N_TROOPS` troops, and castle `i` worth `i + 1`
+    fn default() -> Self {
+        SideRules {
+            budget: N_TROOPS,
+            values: std::array::from_fn(|c| (c + 1) as f32),
+        }
+    }
+}
+
+impl SideRules {
+    pub fn total_value(&self) -> f32 {
+        self.values.iter().sum()
+    }
+
+    /// Whether `distribution` spends exactly this side's budget, with no negative castles
+    pub fn is_valid_distribution(&self, distribution: &[i16; N_CASTLES]) -> bool {
+        core::is_valid_distribution_with_budget(distribution, self.budget)
+    }
+
+    /// A uniform random allocation of this side's budget
+    pub fn generate_with_rng<R: Rng>(&self, rng: &mut R) -> [i16; N_CASTLES] {
+        let split_points = core::gen_split_points_with_budget(self.budget, rng);
+        core::split_points_to_array_with_budget(&split_points, self.budget)
+    }
+}
+
+/// parse_values reads `N_CASTLES` castle values, separated by commas and/or whitespace
+pub fn parse_values(s: &str) -> Result<[f32; N_CASTLES], String> {
+    let values: Vec<&str> = s
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if values.len() != N_CASTLES {
+        return Err(format!(
+            "Expected {N_CASTLES} castle values, got {}",
+            values.len()
+        ));
+    }
+    let mut result = [0.0; N_CASTLES];
+    for (r, v) in result.iter_mut().zip(values) {
+        *r = v
+            .parse()
+            .ok()
+            .filter(|x: &f32| x.is_finite() && *x >= 0.0)
+            .ok_or_else(|| format!("{v:?} is not a castle value"))?;
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct AsymmetricGame {
+    pub a: SideRules,
+    pub b: SideRules,
+}
+
+impl AsymmetricGame {
+    pub fn rules(&self, player: Player) -> &SideRules {
+        match player {
+            Player::A => &self.a,
+            Player::B => &self.b,
+        }
+    }
+
+    /// battle plays `a` against `b`. Whoever sends more troops to a castle takes it, and
+    /// on a tie each side gets half. Each side's points are counted with its own values.
+    pub fn battle(&self, a: &[i16; N_CASTLES], b: &[i16; N_CASTLES]) -> (f32, f32) {
+        let mut a_points = 0.0;
+        let mut b_points = 0.0;
+        for (c, (a_troops, b_troops)) in a.iter().zip(b).enumerate() {
+            match a_troops.cmp(b_troops) {
+                Ordering::Greater => a_points += self.a.values[c],
+                Ordering::Less => b_points += self.b.values[c],
+                Ordering::Equal => {
+                    a_points += self.a.values[c] / 2.0;
+                    b_points += self.b.values[c] / 2.0;
+                }
+            }
+        }
+        (a_points, b_points)
+    }
+
+    /// results says whether each side won, tied or lost `a` against `b`, from its own
+    /// point of view: whether it took more than half of what the castles are worth to
+    /// it. With the default rules, this is the result of `core::battle`.
+    pub fn results(&self, a: &[i16; N_CASTLES], b: &[i16; N_CASTLES]) -> (Ordering, Ordering) {
+        let (a_points, b_points) = self.battle(a, b);
+        (
+            compare_to_half(a_points, self.a.total_value()),
+            compare_to_half(b_points, self.b.total_value()),
+        )
+    }
+
+    /// How `player`, playing `allocation`, does against every member of `field`, which
+    /// plays the other side
+    pub fn score_against_field(
+        &self,
+        player: Player,
+        allocation: &[i16; N_CASTLES],
+        field: &[[i16; N_CASTLES]],
+    ) -> BattleScore {
+        metrics::add_battles(field.len() as u64);
+        let mut score = BattleScore::new();
+        for opponent in field {
+            let result = match player {
+                Player::A => self.results(allocation, opponent).0,
+                Player::B => self.results(opponent, allocation).1,
+            };
+            add_result(&mut score, result);
+        }
+        score
+    }
+
+    /// Like `score_against_field`, but also with the points margin for local search to
+    /// follow, as `local_search::climb_with` wants it
+    fn fitness(
+        &self,
+        player: Player,
+        allocation: [i16; N_CASTLES],
+        field: &[[i16; N_CASTLES]],
+    ) -> (BattleScore, (u32, i32)) {
+        let score = self.score_against_field(player, &allocation, field);
+        let total = self.rules(player).total_value();
+        let margin: f32 = field
+            .iter()
+            .map(|opponent| {
+                let points = match player {
+                    Player::A => self.battle(&allocation, opponent).0,
+                    Player::B => self.battle(opponent, &allocation).1,
+                };
+                2.0 * points - total
+            })
+            .sum();
+        // Castle values need not be whole numbers, so keep two decimal places of margin
+        (
+            score,
+            (victory_points(&score), (100.0 * margin).round() as i32),
+        )
+    }
+
+    /// score_populations plays every member of `a_pool` against every member of
+    /// `b_pool`, and returns each side's records, from its own point of view
+    pub fn score_populations(
+        &self,
+        a_pool: &[[i16; N_CASTLES]],
+        b_pool: &[[i16; N_CASTLES]],
+    ) -> (Vec<BattleScore>, Vec<BattleScore>) {
+        let _span =
+            tracing::info_span!("asymmetric_battles", n_a = a_pool.len(), n_b = b_pool.len())
+                .entered();
+        metrics::add_battles((a_pool.len() * b_pool.len()) as u64);
+        let rows: Vec<Vec<(Ordering, Ordering)>> = a_pool
+            .par_iter()
+            .map(|a| b_pool.iter().map(|b| self.results(a, b)).collect())
+            .collect();
+        let mut a_scores = vec![BattleScore::new(); a_pool.len()];
+        let mut b_scores = vec![BattleScore::new(); b_pool.len()];
+        for (a_score, row) in a_scores.iter_mut().zip(&rows) {
+            for (b_score, &(a_result, b_result)) in b_scores.iter_mut().zip(row) {
+                add_result(a_score, a_result);
+                add_result(b_score, b_result);
+            }
+        }
+        (a_scores, b_scores)
+    }
+
+    /// tournament plays the two pools against each other, and ranks each side by its
+    /// victory points, best first
+    pub fn tournament(
+        &self,
+        a_pool: &[[i16; N_CASTLES]],
+        b_pool: &[[i16; N_CASTLES]],
+    ) -> TwoPopulationRanking {
+        let (a_scores, b_scores) = self.score_populations(a_pool, b_pool);
+        let rank = |pool: &[[i16; N_CASTLES]], scores: Vec<BattleScore>| {
+            let mut ranked: Vec<([i16; N_CASTLES], BattleScore)> =
+                pool.iter().copied().zip(scores).collect();
+            ranked.sort_by_key(|(_, score)| std::cmp::Reverse(victory_points(score)));
+            ranked
+        };
+        TwoPopulationRanking {
+            a: rank(a_pool, a_scores),
+            b: rank(b_pool, b_scores),
+        }
+    }
+
+    /// best_response looks for the allocation of `player`'s budget that does best
+    /// against `field`, which plays the other side, with `config.n_restarts` hill climbs
+    /// from random starting points, in parallel. Restart `i` is seeded with `seed + i`.
+    pub fn best_response(
+        &self,
+        player: Player,
+        field: &[[i16; N_CASTLES]],
+        config: &LocalSearchConfig,
+    ) -> ([i16; N_CASTLES], BattleScore) {
+        let _span = tracing::info_span!("asymmetric_best_response", ?player).entered();
+        (0..config.n_restarts.max(1))
+            .into_par_iter()
+            .map(|i| {
+                let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+                let start = self.rules(player).generate_with_rng(&mut rng);
+                local_search::climb_with(
+                    start,
+                    |p| self.fitness(player, p, field),
+                    config,
+                    &mut rng,
+                )
+            })
+            .max_by_key(|(_, score)| victory_points(score))
+            .expect("There is always at least one restart")
+    }
+
+    /// payoffs gives `player`'s result for each of its strategies (the rows) against each
+    /// of the other side's (the columns): 1 for a win, 0 for a tie, and -1 for a loss
+    pub fn payoffs(
+        &self,
+        player: Player,
+        own: &[[i16; N_CASTLES]],
+        other: &[[i16; N_CASTLES]],
+    ) -> Vec<Vec<f64>> {
+        metrics::add_battles((own.len() * other.len()) as u64);
+        own.par_iter()
+            .map(|p| {
+                other
+                    .iter()
+                    .map(|o| {
+                        let result = match player {
+                            Player::A => self.results(p, o).0,
+                            Player::B => self.results(o, p).1,
+                        };
+                        match result {
+                            Ordering::Greater => 1.0,
+                            Ordering::Less => -1.0,
+                            Ordering::Equal => 0.0,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// security_strategy finds the mixed strategy over `own` that maximises `player`'s
+    /// worst case expected result against `other`, by solving the zero-sum game of its
+    /// own payoffs with `equilibrium::solve`. Its row strategy is `player`'s mix, and its
+    /// value is what that guarantees. When both sides value the castles the same, the
+    /// game is zero-sum, and the two sides' security strategies are an equilibrium.
+    pub fn security_strategy(
+        &self,
+        player: Player,
+        own: &[[i16; N_CASTLES]],
+        other: &[[i16; N_CASTLES]],
+    ) -> Equilibrium {
+        equilibrium::solve(&self.payoffs(player, own, other))
+    }
+}
+
+/// compare_to_half compares `points` to the rest of `total`. Sums of fractional castle
+/// values like 0.1 and 0.2 are not exact in floats, so they count as a tie when they are
+/// within `TIE_TOLERANCE` of `total` of each other.
+fn compare_to_half(points: f32, total: f32) -> Ordering {
+    let margin = points - (total - points);
+    if margin.abs() <= TIE_TOLERANCE * total.abs().max(1.0) {
+        Ordering::Equal
+    } else {
+        margin.total_cmp(&0.0)
+    }
+}
+
+fn add_result(score: &mut BattleScore, result: Ordering) {
+    match result {
+        Ordering::Greater => score.wins += 1,
+        Ordering::Less => score.losses += 1,
+        Ordering::Equal => score.ties += 1,
+    }
+}
+
+/// Both sides of a two population tournament, each best first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TwoPopulationRanking {
+    pub a: Vec<([i16; N_CASTLES], BattleScore)>,
+    pub b: Vec<([i16; N_CASTLES], BattleScore)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_battle() {
+        // The default rules are the original game
+        let game = AsymmetricGame::default();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..100 {
+            let a = core::generate_uniform_random_distribution_with_rng(&mut rng);
+            let b = core::generate_uniform_random_distribution_with_rng(&mut rng);
+            assert_eq!(core::battle(a, b), game.battle(&a, &b));
+            let (p1, p2) = core::battle(a, b);
+            assert_eq!((p1.total_cmp(&p2), p2.total_cmp(&p1)), game.results(&a, &b));
+        }
+
+        // B only cares about the last castle, so B can lose most of the points A counts
+        // and still win by its own values
+        let game = AsymmetricGame {
+            a: SideRules::default(),
+            b: SideRules {
+                budget: 50,
+                values: [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            },
+        };
+        let a: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let b: [i16; 10] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 50];
+        assert!(game.b.is_valid_distribution(&b));
+        assert_eq!((45.0, 1.0), game.battle(&a, &b));
+        assert_eq!((Ordering::Greater, Ordering::Greater), game.results(&a, &b));
+
+        // A ties castle 1, wins castles 2-7 and loses the rest, which is exactly half of
+        // its values, even though the float sums come out a little apart
+        let game = AsymmetricGame {
+            a: SideRules {
+                values: [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0],
+                ..Default::default()
+            },
+            b: SideRules::default(),
+        };
+        let a: [i16; 10] = [10, 15, 15, 15, 15, 15, 15, 0, 0, 0];
+        let b: [i16; 10] = [10, 0, 0, 0, 0, 0, 0, 30, 30, 30];
+        assert_eq!(Ordering::Equal, game.results(&a, &b).0);
+    }
+
+    #[test]
+    fn test_two_populations() {
+        let game = AsymmetricGame {
+            a: SideRules::default(),
+            b: SideRules {
+                budget: 60,
+                ..Default::default()
+            },
+        };
+        let mut rng = StdRng::seed_from_u64(2);
+        let a_pool: Vec<[i16; 10]> = (0..30)
+            .map(|_| game.a.generate_with_rng(&mut rng))
+            .collect();
+        let b_pool: Vec<[i16; 10]> = (0..30)
+            .map(|_| game.b.generate_with_rng(&mut rng))
+            .collect();
+        assert!(b_pool.iter().all(|b| game.b.is_valid_distribution(b)));
+
+        let ranking = game.tournament(&a_pool, &b_pool);
+        let a_wins: u32 = ranking.a.iter().map(|(_, s)| s.wins).sum();
+        let b_losses: u32 = ranking.b.iter().map(|(_, s)| s.losses).sum();
+        // Both sides value the castles the same, so one side's wins are the other's losses
+        assert_eq!(a_wins, b_losses);
+        assert!(ranking
+            .a
+            .windows(2)
+            .all(|w| victory_points(&w[0].1) >= victory_points(&w[1].1)));
+
+        let config = LocalSearchConfig {
+            n_restarts: 2,
+            ..Default::default()
+        };
+        let (best, score) = game.best_response(Player::B, &a_pool, &config);
+        assert!(game.b.is_valid_distribution(&best));
+        assert!(victory_points(&score) >= victory_points(&ranking.b[0].1));
+
+        let eq = game.security_strategy(Player::A, &a_pool[..10], &b_pool[..10]);
+        assert!(eq.value >= -1.0 && eq.value <= 1.0);
+    }
+}
@@ -0,0 +1,88 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+
+use crate::core::{self, BattleScore, N_CASTLES};
+use crate::local_search::{self, LocalSearchConfig, Strategy};
+
+/// victory_points scores a result the same way as `final_battle::tournament`: 1 point for
+/// a win and half a point for a tie, doubled so that it stays an integer
+pub fn victory_points(score: &BattleScore) -> u32 {
+    2 * score.wins + score.ties
+}
+
+/// troop_transfers gives every allocation that can be made from `player` by moving
+/// between 1 and `max_transfer` troops from one castle to another
+pub fn troop_transfers(player: [i16; 10], max_transfer: i16) -> impl Iterator<Item = [i16; 10]> {
+    (0..N_CASTLES).flat_map(move |from| {
+        (0..N_CASTLES)
+            .filter(move |&to| to != from)
+            .flat_map(move |to| {
+                (1..=player[from].min(max_transfer)).map(move |n| {
+                    let mut moved = player;
+                    moved[from] -= n;
+                    moved[to] += n;
+                    moved
+                })
+            })
+    })
+}
+
+/// best_response looks for the allocation that does best against `field`, by steepest
+/// ascent hill climbing over every troop transfer from `n_restarts` uniform random starting
+/// points, in parallel. Restart `i` is seeded with `seed + i`.
+///
+/// Returns the best allocation found, and how it did against the field.
+pub fn best_response(
+    field: &[[i16; 10]],
+    n_restarts: usize,
+    seed: u64,
+) -> ([i16; 10], BattleScore) {
+    let _span = tracing::info_span!("best_response", n_restarts).entered();
+    let config = LocalSearchConfig {
+        strategy: Strategy::SteepestAscent,
+        max_transfer: core::N_TROOPS,
+        tabu_tenure: 0,
+        n_restarts,
+        seed,
+        ..Default::default()
+    };
+    (0..n_restarts.max(1))
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+            let start = core::generate_uniform_random_distribution_with_rng(&mut rng);
+            local_search::climb(start, field, &config, &mut rng)
+        })
+        .max_by_key(|(_, score)| victory_points(score))
+        .expect("There is always at least one restart")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_troop_transfers() {
+        let p: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(9, troop_transfers(p, 1).count());
+        assert_eq!(18, troop_transfers(p, 2).count());
+
+        let p: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let moves: Vec<[i16; 10]> = troop_transfers(p, 1).collect();
+        assert_eq!(90, moves.len());
+        assert!(moves.iter().all(core::is_valid_distribution));
+        assert_eq!(900, troop_transfers(p, 100).count());
+    }
+
+    #[test]
+    fn test_best_response_beats_field() {
+        let field: Vec<[i16; 10]> = vec![
+            [10, 10, 10, 10, 10, 10, 10, 10, 10, 10],
+            [0, 0, 0, 0, 0, 20, 20, 20, 20, 20],
+        ];
+        let (player, score) = best_response(&field, 4, 1);
+        assert!(core::is_valid_distribution(&player));
+        assert_eq!(2, score.wins);
+    }
+}
@@ -0,0 +1,223 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use rustc_hash::FxHashSet;
+
+use crate::final_battle;
+
+/// z value for a two sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// How many places from the top count as "reaching the top 10"
+const TOP_N: usize = 10;
+
+/// A point estimate together with its 95% confidence interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub mean: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// How a single candidate did across all of the bootstrap tournaments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CandidateStats {
+    pub candidate: [i16; 10],
+    /// Probability of winning the tournament
+    pub p_win: Estimate,
+    /// Probability of finishing in the top 10
+    pub p_top_10: Estimate,
+    /// Finishing percentile, where 100 is first place and 0 is last place
+    pub percentile: Estimate,
+}
+
+/// wilson_interval gives the Wilson score interval for a proportion, which behaves much
+/// better than the normal approximation when `successes` is close to 0 or `n`
+fn wilson_interval(successes: usize, n: usize) -> Estimate {
+    let n_f = n as f64;
+    let p_hat = successes as f64 / n_f;
+    let z2 = Z_95 * Z_95;
+    let denom = 1.0 + z2 / n_f;
+    let center = (p_hat + z2 / (2.0 * n_f)) / denom;
+    let half_width = Z_95 * (p_hat * (1.0 - p_hat) / n_f + z2 / (4.0 * n_f * n_f)).sqrt() / denom;
+    Estimate {
+        mean: p_hat,
+        lower: (center - half_width).max(0.0),
+        upper: (center + half_width).min(1.0),
+    }
+}
+
+/// mean_interval gives the sample mean with a normal approximation confidence interval
+fn mean_interval(samples: &[f64]) -> Estimate {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    if samples.len() < 2 {
+        return Estimate {
+            mean,
+            lower: mean,
+            upper: mean,
+        };
+    }
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let half_width = Z_95 * (variance / n).sqrt();
+    Estimate {
+        mean,
+        lower: mean - half_width,
+        upper: mean + half_width,
+    }
+}
+
+/// Run one bootstrap replicate: the candidates enter a tournament against `field_size`
+/// players resampled (with replacement) from `field`. Returns each candidate's finishing
+/// position, counted from last place, and how many players were left after duplicates
+/// were merged. Returns `None` if fewer than 2 different players were drawn, since there
+/// is no tournament to play.
+fn run_replicate(
+    candidates: &[[i16; 10]],
+    field: &[[i16; 10]],
+    field_size: usize,
+    seed: u64,
+) -> Option<(Vec<usize>, usize)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pool: Vec<[i16; 10]> = candidates.to_vec();
+    pool.extend((0..field_size).map(|_| *field.choose(&mut rng).expect("field is empty")));
+    if pool.iter().collect::<FxHashSet<_>>().len() < 2 {
+        return None;
+    }
+
+    let res = final_battle::tournament(&pool, false);
+    let places = candidates
+        .iter()
+        .map(|c| {
+            res.iter()
+                .position(|p| p == c)
+                .expect("A candidate went missing from the tournament")
+        })
+        .collect();
+    Some((places, res.len()))
+}
+
+/// bootstrap estimates how stable the result of a tournament is. It runs `n_resamples`
+/// tournaments in parallel, each with all of the `candidates` plus an opponent field of
+/// `field_size` players resampled with replacement from `field`. Replicate `i` is
+/// seeded with `seed + i`, so the same seed always gives the same answer.
+///
+/// Every replicate is a full `final_battle::tournament`, which is cubic in the number of
+/// players, so resampling a smaller field than `field.len()` is much cheaper.
+///
+/// Replicates that drew fewer than 2 different players are skipped. Returns the stats for
+/// each candidate, in the same order as `candidates`, or nothing if every replicate was
+/// skipped.
+pub fn bootstrap(
+    candidates: &[[i16; 10]],
+    field: &[[i16; 10]],
+    field_size: usize,
+    n_resamples: usize,
+    seed: u64,
+) -> Vec<CandidateStats> {
+    assert!(n_resamples > 0, "Need at least one resample");
+    assert!(!field.is_empty(), "Cannot resample from an empty field");
+    assert!(
+        candidates.len() + field_size >= 2,
+        "A tournament needs at least 2 players"
+    );
+    let _span = tracing::info_span!("bootstrap", n_resamples, field_size).entered();
+
+    let replicates: Vec<(Vec<usize>, usize)> = (0..n_resamples)
+        .into_par_iter()
+        .filter_map(|i| run_replicate(candidates, field, field_size, seed.wrapping_add(i as u64)))
+        .collect();
+    if replicates.is_empty() {
+        return Vec::new();
+    }
+    let n_replicates = replicates.len();
+
+    candidates
+        .iter()
+        .enumerate()
+        .map(|(c_idx, &candidate)| {
+            let mut n_wins = 0;
+            let mut n_top = 0;
+            let mut percentiles = Vec::with_capacity(n_replicates);
+            for (places, n_players) in &replicates {
+                let place = places[c_idx];
+                if place + 1 == *n_players {
+                    n_wins += 1;
+                }
+                if place + TOP_N >= *n_players {
+                    n_top += 1;
+                }
+                percentiles.push(100.0 * place as f64 / (*n_players - 1).max(1) as f64);
+            }
+            CandidateStats {
+                candidate,
+                p_win: wilson_interval(n_wins, n_replicates),
+                p_top_10: wilson_interval(n_top, n_replicates),
+                percentile: mean_interval(&percentiles),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wilson_interval() {
+        let got = wilson_interval(50, 100);
+        assert_eq!(0.5, got.mean);
+        assert!((got.lower - 0.4038).abs() < 1e-3);
+        assert!((got.upper - 0.5962).abs() < 1e-3);
+
+        // Never goes outside of [0, 1], even at the edges
+        let got = wilson_interval(10, 10);
+        assert_eq!(1.0, got.mean);
+        assert!(got.lower > 0.6 && got.upper <= 1.0);
+    }
+
+    #[test]
+    fn test_bootstrap_dominant_candidate() {
+        // An even spread beats every strategy that puts all of its troops on one of the
+        // first 9 castles, so it should win every single resample
+        let even: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let field: Vec<[i16; 10]> = (0..9)
+            .map(|c| {
+                let mut p = [0_i16; 10];
+                p[c] = 100;
+                p
+            })
+            .collect();
+        let got = bootstrap(&[even], &field, field.len(), 20, 42);
+        assert_eq!(1, got.len());
+        assert_eq!(1.0, got[0].p_win.mean);
+        assert_eq!(1.0, got[0].p_top_10.mean);
+        assert_eq!(100.0, got[0].percentile.mean);
+
+        // And against a smaller resampled field too
+        let got = bootstrap(&[even], &field, 3, 20, 42);
+        assert_eq!(1.0, got[0].p_win.mean);
+    }
+
+    #[test]
+    fn test_bootstrap_skips_degenerate_replicates() {
+        let even: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let mut other = [0_i16; 10];
+        other[9] = 100;
+
+        // Drawing the candidate itself leaves one player, so only draws of `other` count
+        let got = bootstrap(&[even], &[even, other], 1, 40, 1);
+        assert_eq!(1, got.len());
+        assert_eq!(1.0, got[0].p_win.mean);
+
+        // Every replicate is the candidate against itself
+        assert!(bootstrap(&[even], &[even], 1, 10, 1).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2 players")]
+    fn test_bootstrap_needs_two_players() {
+        bootstrap(&[[10; 10]], &[[10; 10]], 0, 10, 1);
+    }
+}
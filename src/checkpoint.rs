@@ -0,0 +1,119 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fixed_point::{Precision, MAX_DECIMALS};
+
+/// Everything needed to reproduce a run of the tournaments in `main`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub tournament_size: usize,
+    pub n_tournaments: usize,
+    /// The pool for tournament `i` is generated by an RNG seeded with `seed + i`, so the
+    /// seed, together with how many tournaments have finished, is the entire RNG state
+    /// of a run
+    pub seed: u64,
+    /// How many decimal places of a troop the allocations are counted in. Checkpoints
+    /// from before fractional troops were supported are in whole troops.
+    #[serde(default)]
+    pub decimals: u32,
+}
+
+impl RunConfig {
+    pub fn precision(&self) -> Precision {
+        Precision::new(self.decimals)
+    }
+}
+
+/// A snapshot of a partially finished run, which can be written to disk and picked back
+/// up later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub config: RunConfig,
+    /// Winners of the tournaments that have finished so far, in tournament order
+    pub winners: Vec<[i16; 10]>,
+}
+
+impl Checkpoint {
+    pub fn new(config: RunConfig) -> Self {
+        Checkpoint {
+            config,
+            winners: Vec::with_capacity(config.n_tournaments),
+        }
+    }
+
+    /// Index of the next tournament to run
+    pub fn next_tournament(&self) -> usize {
+        self.winners.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.winners.len() >= self.config.n_tournaments
+    }
+
+    /// save writes the checkpoint to `path` as JSON. It is written to a temporary file
+    /// first and then moved into place, so a run killed in the middle of saving never
+    /// leaves a half written checkpoint behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let json = serde_json::to_vec(self)?;
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// load reads a checkpoint written by `save`. It is an error for the checkpoint to
+    /// count troops in more than `MAX_DECIMALS` decimal places.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read(path)?;
+        let checkpoint: Checkpoint = serde_json::from_slice(&json)?;
+        if checkpoint.config.decimals > MAX_DECIMALS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: troops are counted in {} decimal places, but at most {MAX_DECIMALS} are supported",
+                    path.display(),
+                    checkpoint.config.decimals
+                ),
+            ));
+        }
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let config = RunConfig {
+            tournament_size: 50,
+            n_tournaments: 3,
+            seed: 12345,
+            decimals: 0,
+        };
+        let mut checkpoint = Checkpoint::new(config);
+        checkpoint
+            .winners
+            .push([10, 10, 10, 10, 10, 10, 10, 10, 10, 10]);
+        checkpoint.winners.push([0, 0, 0, 0, 0, 0, 0, 0, 0, 100]);
+        assert_eq!(2, checkpoint.next_tournament());
+        assert!(!checkpoint.is_finished());
+
+        let path = std::env::temp_dir().join(format!(
+            "rs_battle_for_nation_checkpoint_{}.json",
+            std::process::id()
+        ));
+        checkpoint.save(&path).unwrap();
+        let got = Checkpoint::load(&path).unwrap();
+        assert_eq!(checkpoint, got);
+
+        // Too many decimal places is an error, rather than a panic later on
+        checkpoint.config.decimals = MAX_DECIMALS + 1;
+        checkpoint.save(&path).unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
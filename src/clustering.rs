@@ -0,0 +1,360 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::best_response::victory_points;
+use crate::core::{BattleScore, N_CASTLES, N_TROOPS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Distance {
+    /// How many troops differ, castle by castle
+    L1,
+    /// How many troops have to be moved, times how many castles they are moved along, to
+    /// turn one allocation into the other. Unlike L1, moving troops to a neighbouring
+    /// castle counts for less than moving them to the other end.
+    EarthMovers,
+}
+
+impl Distance {
+    pub fn between(self, a: &[f64; N_CASTLES], b: &[f64; N_CASTLES]) -> f64 {
+        match self {
+            Distance::L1 => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+            // In one dimension, this is the L1 distance between the running totals
+            Distance::EarthMovers => a
+                .iter()
+                .zip(b)
+                .scan(0.0, |carried, (x, y)| {
+                    *carried += x - y;
+                    Some(f64::abs(*carried))
+                })
+                .sum(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Method {
+    /// The center of each cluster is the mean of its members
+    KMeans,
+    /// The center of each cluster is the member with the smallest total distance to the
+    /// rest of it
+    KMedoids,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ClusterConfig {
+    pub k: usize,
+    pub method: Method,
+    pub distance: Distance,
+    pub max_iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        ClusterConfig {
+            k: 6,
+            method: Method::KMedoids,
+            distance: Distance::EarthMovers,
+            max_iterations: 100,
+            seed: 0,
+        }
+    }
+}
+
+/// A group of similar strategies
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Archetype {
+    /// A short description of the centroid, like "top-heavy, abandon castles 1-3"
+    pub label: String,
+    /// The mean of the members
+    pub centroid: [f64; N_CASTLES],
+    /// The member closest to the centroid, or with k-medoids, the medoid itself
+    pub representative: [i16; N_CASTLES],
+    pub size: usize,
+    pub mean_wins: f64,
+    /// Mean of 1 point per win and half a point per tie
+    pub mean_victory_points: f64,
+}
+
+fn to_f64(p: &[i16; N_CASTLES]) -> [f64; N_CASTLES] {
+    p.map(f64::from)
+}
+
+fn mean(points: &[[f64; N_CASTLES]], members: &[usize]) -> [f64; N_CASTLES] {
+    let mut total = [0.0; N_CASTLES];
+    for &idx in members {
+        for (t, x) in total.iter_mut().zip(&points[idx]) {
+            *t += x;
+        }
+    }
+    total.map(|t| t / members.len().max(1) as f64)
+}
+
+/// k-means++ starting centers: the first is picked at random, and each one after that
+/// with a chance proportional to its squared distance from the nearest center so far
+fn initial_centers<R: Rng>(
+    points: &[[f64; N_CASTLES]],
+    k: usize,
+    distance: Distance,
+    rng: &mut R,
+) -> Vec<usize> {
+    let mut centers = vec![rng.gen_range(0..points.len())];
+    while centers.len() < k {
+        let weights: Vec<f64> = points
+            .iter()
+            .map(|p| {
+                centers
+                    .iter()
+                    .map(|&c| distance.between(p, &points[c]))
+                    .fold(f64::INFINITY, f64::min)
+                    .powi(2)
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total == 0.0 {
+            // Every point is already a center
+            break;
+        }
+        let mut target = rng.gen::<f64>() * total;
+        let next = weights
+            .iter()
+            .position(|&w| {
+                target -= w;
+                target <= 0.0
+            })
+            .unwrap_or(points.len() - 1);
+        centers.push(next);
+    }
+    centers
+}
+
+fn nearest(point: &[f64; N_CASTLES], centers: &[[f64; N_CASTLES]], distance: Distance) -> usize {
+    centers
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            distance
+                .between(point, a)
+                .total_cmp(&distance.between(point, b))
+        })
+        .map(|(idx, _)| idx)
+        .expect("There is always at least one center")
+}
+
+/// The member of `members` with the smallest total distance to the rest of them
+fn medoid(points: &[[f64; N_CASTLES]], members: &[usize], distance: Distance) -> usize {
+    *members
+        .iter()
+        .min_by(|&&a, &&b| {
+            let total = |x: usize| -> f64 {
+                members
+                    .iter()
+                    .map(|&m| distance.between(&points[x], &points[m]))
+                    .sum()
+            };
+            total(a).total_cmp(&total(b))
+        })
+        .expect("Clusters are never empty")
+}
+
+/// cluster groups `points` into at most `config.k` clusters. Returns the index of the
+/// cluster of every point.
+pub fn cluster(points: &[[f64; N_CASTLES]], config: &ClusterConfig) -> Vec<usize> {
+    if points.is_empty() {
+        return Vec::new();
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut centers: Vec<[f64; N_CASTLES]> = initial_centers(
+        points,
+        config.k.clamp(1, points.len()),
+        config.distance,
+        &mut rng,
+    )
+    .iter()
+    .map(|&idx| points[idx])
+    .collect();
+    let mut assignments = vec![usize::MAX; points.len()];
+
+    for _ in 0..config.max_iterations.max(1) {
+        let new_assignments: Vec<usize> = points
+            .iter()
+            .map(|p| nearest(p, &centers, config.distance))
+            .collect();
+        if new_assignments == assignments {
+            break;
+        }
+        assignments = new_assignments;
+
+        for (c, center) in centers.iter_mut().enumerate() {
+            let members: Vec<usize> = (0..points.len()).filter(|&i| assignments[i] == c).collect();
+            if members.is_empty() {
+                // Keep the old center, and let it pick up points on the next pass
+                continue;
+            }
+            *center = match config.method {
+                Method::KMeans => mean(points, &members),
+                Method::KMedoids => points[medoid(points, &members, config.distance)],
+            };
+        }
+    }
+    assignments
+}
+
+/// describe gives a short name to the shape of an allocation
+pub fn describe(centroid: &[f64; N_CASTLES]) -> String {
+    let total: f64 = centroid.iter().sum();
+    let mut traits = Vec::new();
+
+    let top_share = centroid[N_CASTLES / 2..].iter().sum::<f64>() / total;
+    let spread = centroid.iter().cloned().fold(f64::MIN, f64::max)
+        - centroid.iter().cloned().fold(f64::MAX, f64::min);
+    if spread <= total / N_CASTLES as f64 {
+        return "even spread".to_string();
+    }
+    if top_share >= 0.7 {
+        traits.push("top-heavy".to_string());
+    } else if top_share <= 0.3 {
+        traits.push("bottom-heavy".to_string());
+    }
+
+    // Castles that get less than a troop on average are counted as abandoned. The
+    // centroid adds up to the budget, so this works for fractions of a troop too
+    let troop = total / N_TROOPS as f64;
+    let abandoned = centroid.iter().take_while(|&&t| t < troop).count();
+    match abandoned {
+        0 => {}
+        1 => traits.push("abandon castle 1".to_string()),
+        n => traits.push(format!("abandon castles 1-{n}")),
+    }
+
+    // Otherwise, say where most of the troops go
+    if abandoned == 0 {
+        let mut heaviest: Vec<usize> = (0..N_CASTLES).collect();
+        heaviest.sort_by(|&a, &b| centroid[b].total_cmp(&centroid[a]));
+        let mut top3: Vec<usize> = heaviest.into_iter().take(3).map(|c| c + 1).collect();
+        top3.sort();
+        traits.push(format!(
+            "focus on castles {}",
+            top3.iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    traits.join(", ")
+}
+
+/// archetypes clusters `players`, and summarises each cluster, largest first
+pub fn archetypes(
+    players: &[([i16; N_CASTLES], BattleScore)],
+    config: &ClusterConfig,
+) -> Vec<Archetype> {
+    let _span =
+        tracing::info_span!("clustering", n_players = players.len(), k = config.k).entered();
+    let points: Vec<[f64; N_CASTLES]> = players.iter().map(|(p, _)| to_f64(p)).collect();
+    let assignments = cluster(&points, config);
+    let n_clusters = assignments.iter().max().map_or(0, |&c| c + 1);
+
+    let mut result: Vec<Archetype> = (0..n_clusters)
+        .filter_map(|c| {
+            let members: Vec<usize> = (0..players.len())
+                .filter(|&i| assignments[i] == c)
+                .collect();
+            if members.is_empty() {
+                return None;
+            }
+            let centroid = mean(&points, &members);
+            let representative = match config.method {
+                Method::KMeans => *members
+                    .iter()
+                    .min_by(|&&a, &&b| {
+                        config
+                            .distance
+                            .between(&points[a], &centroid)
+                            .total_cmp(&config.distance.between(&points[b], &centroid))
+                    })
+                    .expect("Clusters are never empty"),
+                Method::KMedoids => medoid(&points, &members, config.distance),
+            };
+            let size = members.len();
+            Some(Archetype {
+                label: describe(&centroid),
+                centroid,
+                representative: players[representative].0,
+                size,
+                mean_wins: members
+                    .iter()
+                    .map(|&i| players[i].1.wins as f64)
+                    .sum::<f64>()
+                    / size as f64,
+                mean_victory_points: members
+                    .iter()
+                    .map(|&i| victory_points(&players[i].1) as f64 / 2.0)
+                    .sum::<f64>()
+                    / size as f64,
+            })
+        })
+        .collect();
+    result.sort_by_key(|a| std::cmp::Reverse(a.size));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distances() {
+        let a = to_f64(&[100, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let b = to_f64(&[0, 100, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let c = to_f64(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 100]);
+        assert_eq!(200.0, Distance::L1.between(&a, &b));
+        assert_eq!(200.0, Distance::L1.between(&a, &c));
+        assert_eq!(100.0, Distance::EarthMovers.between(&a, &b));
+        assert_eq!(900.0, Distance::EarthMovers.between(&a, &c));
+        assert_eq!(0.0, Distance::EarthMovers.between(&c, &c));
+    }
+
+    #[test]
+    fn test_describe() {
+        let centroid = to_f64(&[0, 0, 0, 0, 0, 20, 20, 20, 20, 20]);
+        assert_eq!("top-heavy, abandon castles 1-5", describe(&centroid));
+        // The same in tenths of a troop, where 5 tenths is less than a troop
+        let centroid = to_f64(&[5, 5, 5, 5, 5, 195, 195, 195, 195, 195]);
+        assert_eq!("top-heavy, abandon castles 1-5", describe(&centroid));
+    }
+
+    #[test]
+    fn test_archetypes() {
+        // Two obvious groups, with small variations
+        let mut players = Vec::new();
+        for i in 0..10 {
+            players.push([10 + i % 2, 10 - i % 2, 10, 10, 10, 10, 10, 10, 10, 10]);
+            players.push([0, 0, 0, 0, 0, 20 + i % 3, 20, 20, 20, 20 - i % 3]);
+        }
+        let players: Vec<([i16; 10], BattleScore)> = players
+            .into_iter()
+            .map(|p| (p, BattleScore::default()))
+            .collect();
+        for method in [Method::KMeans, Method::KMedoids] {
+            for distance in [Distance::L1, Distance::EarthMovers] {
+                let config = ClusterConfig {
+                    k: 2,
+                    method,
+                    distance,
+                    ..Default::default()
+                };
+                let got = archetypes(&players, &config);
+                assert_eq!(2, got.len());
+                assert!(got.iter().all(|a| a.size == 10));
+                let mut labels: Vec<&str> = got.iter().map(|a| a.label.as_str()).collect();
+                labels.sort();
+                assert_eq!(
+                    vec!["even spread", "top-heavy, abandon castles 1-5"],
+                    labels
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rustc_hash::FxHashSet;
+use serde::Serialize;
+
+use crate::best_response::victory_points;
+use crate::core::{self, BattleScore, N_CASTLES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CoevolutionConfig {
+    pub population_size: usize,
+    pub n_generations: usize,
+    /// The most past champions the hall of fame holds
+    pub archive_size: usize,
+    /// How many of the best of each generation survive into the next one, and have
+    /// children to fill the rest of it
+    pub n_parents: usize,
+    /// Children move each split point of their parent by up to this many troops
+    pub mutation_range: i16,
+    /// A champion is only added to the hall of fame if it is at least this `l1_distance`
+    /// from everything already in it
+    pub min_archive_distance: i16,
+    pub seed: u64,
+}
+
+impl Default for CoevolutionConfig {
+    fn default() -> Self {
+        CoevolutionConfig {
+            population_size: 200,
+            n_generations: 100,
+            archive_size: 50,
+            n_parents: 20,
+            mutation_range: 5,
+            min_archive_distance: 10,
+            seed: 0,
+        }
+    }
+}
+
+/// How one generation went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub champion: [i16; N_CASTLES],
+    /// How the champion did against the hall of fame and the rest of the population
+    pub champion_score: BattleScore,
+    /// Whether the champion was added to the hall of fame
+    pub archived: bool,
+    pub archive_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CoevolutionResult {
+    /// The hall of fame, in the order the champions were added, apart from any that have
+    /// since been replaced
+    pub archive: Vec<[i16; N_CASTLES]>,
+    /// The best of the final population
+    pub champion: [i16; N_CASTLES],
+    pub history: Vec<GenerationStats>,
+}
+
+/// The `l1_distance` from `player` to its nearest neighbour in `others`, skipping index
+/// `skip`
+fn nearest_distance(
+    player: &[i16; N_CASTLES],
+    others: &[[i16; N_CASTLES]],
+    skip: Option<usize>,
+) -> i16 {
+    others
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| Some(*idx) != skip)
+        .map(|(_, other)| core::l1_distance(player, other))
+        .min()
+        .unwrap_or(i16::MAX)
+}
+
+/// insert_diverse adds `candidate` to `archive`, unless something within `min_distance` of
+/// it is already there. Once `archive` holds `max_size` players, the candidate instead
+/// replaces the most crowded member, the one closest to its own nearest neighbour, but
+/// only if the candidate would be less crowded than it. Returns whether it was added.
+pub fn insert_diverse(
+    archive: &mut Vec<[i16; N_CASTLES]>,
+    candidate: [i16; N_CASTLES],
+    max_size: usize,
+    min_distance: i16,
+) -> bool {
+    if max_size == 0 {
+        return false;
+    }
+    let candidate_distance = nearest_distance(&candidate, archive, None);
+    if candidate_distance < min_distance.max(1) {
+        return false;
+    }
+    if archive.len() < max_size {
+        archive.push(candidate);
+        return true;
+    }
+
+    let (crowded_idx, crowded_distance) = (0..archive.len())
+        .map(|idx| (idx, nearest_distance(&archive[idx], archive, Some(idx))))
+        .min_by_key(|&(_, distance)| distance)
+        .expect("The archive is not empty");
+    // Without the crowded member, the candidate might be closer to something else
+    let mut without = archive.clone();
+    without.remove(crowded_idx);
+    if nearest_distance(&candidate, &without, None) <= crowded_distance {
+        return false;
+    }
+    archive.remove(crowded_idx);
+    archive.push(candidate);
+    true
+}
+
+/// coevolve evolves a population that is scored, every generation, by a round robin
+/// between the population and a hall of fame of past champions. The best of each
+/// generation is offered to the hall of fame with `insert_diverse`, so the opponents keep
+/// changing as the population does, instead of being a fixed field.
+pub fn coevolve(config: &CoevolutionConfig) -> CoevolutionResult {
+    let _span = tracing::info_span!("coevolution", n_generations = config.n_generations).entered();
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let population_size = config.population_size.max(2);
+    let n_parents = config.n_parents.clamp(1, population_size);
+
+    let mut population: Vec<[i16; N_CASTLES]> = (0..population_size)
+        .map(|_| core::generate_uniform_random_distribution_with_rng(&mut rng))
+        .collect();
+    let mut archive: Vec<[i16; N_CASTLES]> = Vec::new();
+    let mut history = Vec::with_capacity(config.n_generations);
+    let mut champion = population[0];
+
+    for generation in 0..config.n_generations.max(1) {
+        let _span = tracing::debug_span!("generation", generation).entered();
+        let players: FxHashSet<[i16; N_CASTLES]> =
+            population.iter().chain(archive.iter()).copied().collect();
+        let scores = core::run_battles_set(&players);
+
+        // Best first. Ties are broken by the allocation, so that seeded runs repeat
+        let mut ranked: Vec<([i16; N_CASTLES], BattleScore)> = population
+            .iter()
+            .map(|p| (*p, scores.get(p).copied().unwrap_or_default()))
+            .collect();
+        ranked.sort_by(|(p1, s1), (p2, s2)| {
+            victory_points(s2).cmp(&victory_points(s1)).then(p1.cmp(p2))
+        });
+        ranked.dedup_by_key(|(p, _)| *p);
+
+        let (best, best_score) = ranked[0];
+        champion = best;
+        let archived = insert_diverse(
+            &mut archive,
+            best,
+            config.archive_size,
+            config.min_archive_distance,
+        );
+        history.push(GenerationStats {
+            generation,
+            champion: best,
+            champion_score: best_score,
+            archived,
+            archive_size: archive.len(),
+        });
+
+        // The parents survive, and their children fill out the rest of the population
+        let parents: Vec<[i16; N_CASTLES]> =
+            ranked.iter().take(n_parents).map(|(p, _)| *p).collect();
+        let n_children = population_size - parents.len();
+        population = parents.clone();
+        for (idx, parent) in parents.iter().enumerate() {
+            // Share the children out as evenly as possible, with any left over going to
+            // the best parents
+            let share = n_children / parents.len() + usize::from(idx < n_children % parents.len());
+            population.extend(core::generate_random_children_with_rng(
+                *parent,
+                share,
+                config.mutation_range.max(1),
+                &mut rng,
+            ));
+        }
+    }
+
+    CoevolutionResult {
+        archive,
+        champion,
+        history,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_diverse() {
+        let mut archive = Vec::new();
+        let even: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        assert!(insert_diverse(&mut archive, even, 2, 10));
+        // Too close to what is already there
+        let mut near = even;
+        near[0] -= 2;
+        near[9] += 2;
+        assert!(!insert_diverse(&mut archive, near, 2, 10));
+
+        let far: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(insert_diverse(&mut archive, far, 2, 10));
+        assert_eq!(vec![even, far], archive);
+
+        // The archive is full. Something far from both replaces one of them, since that
+        // spreads the archive out more, but something in between does not
+        let other: [i16; 10] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 100];
+        assert!(insert_diverse(&mut archive, other, 2, 10));
+        assert_eq!(vec![far, other], archive);
+        let middle: [i16; 10] = [50, 5, 5, 5, 5, 5, 5, 5, 5, 10];
+        assert!(!insert_diverse(&mut archive, middle, 2, 10));
+        assert_eq!(vec![far, other], archive);
+    }
+
+    #[test]
+    fn test_coevolve() {
+        let config = CoevolutionConfig {
+            population_size: 30,
+            n_generations: 10,
+            archive_size: 5,
+            n_parents: 5,
+            seed: 3,
+            ..Default::default()
+        };
+        let res = coevolve(&config);
+        assert_eq!(10, res.history.len());
+        assert!(!res.archive.is_empty() && res.archive.len() <= 5);
+        assert!(res.archive.iter().all(core::is_valid_distribution));
+        assert!(core::is_valid_distribution(&res.champion));
+        for (i, p1) in res.archive.iter().enumerate() {
+            for p2 in &res.archive[i + 1..] {
+                assert!(core::l1_distance(p1, p2) >= config.min_archive_distance);
+            }
+        }
+
+        // Seeded runs are the same
+        assert_eq!(res, coevolve(&config));
+    }
+}
@@ -1,16 +1,45 @@
-extern crate test;
-
 use itertools::Itertools;
 use rand::Rng;
 use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
+use crate::metrics;
+
+/// How many castles there are. The castle at index `i` is worth `i + 1` points.
+pub const N_CASTLES: usize = 10;
+
+/// How many troops each player spreads across the castles
+pub const N_TROOPS: i16 = 100;
+
+/// is_valid_distribution checks that no castle has a negative number of troops, and that
+/// all `N_TROOPS` troops are used
+pub fn is_valid_distribution(distribution: &[i16; N_CASTLES]) -> bool {
+    is_valid_distribution_with_budget(distribution, N_TROOPS)
+}
+
+/// is_valid_distribution_with_budget is the same as is_valid_distribution, but checks that
+/// exactly `budget` troops are used. The sum is taken in `i32`, so large castles cannot
+/// overflow and wrap around to the budget.
+pub fn is_valid_distribution_with_budget(distribution: &[i16; N_CASTLES], budget: i16) -> bool {
+    distribution.iter().all(|&t| t >= 0)
+        && distribution.iter().map(|&t| t as i32).sum::<i32>() == budget as i32
+}
+
 /// battle will compare two length 10 arrays and see who wins
 pub fn battle(p1: [i16; 10], p2: [i16; 10]) -> (f32, f32) {
+    battle_slices(&p1, &p2)
+}
+
+/// battle_slices is the same as battle, but for any number of castles, so it can be used
+/// for smaller variants of the game. The castle at index `i` is still worth `i + 1`.
+#[inline]
+pub fn battle_slices(p1: &[i16], p2: &[i16]) -> (f32, f32) {
+    debug_assert_eq!(p1.len(), p2.len());
     let mut p1_score = 0_f32;
     let mut p2_score = 0_f32;
 
-    for (castle_num, (p1, p2)) in p1.iter().zip(p2.iter()).enumerate() {
+    for (castle_num, (p1, p2)) in p1.iter().zip(p2).enumerate() {
         let score = (castle_num + 1) as f32;
         match p1.cmp(p2) {
             Ordering::Greater => p1_score += score,
@@ -25,43 +54,103 @@ pub fn battle(p1: [i16; 10], p2: [i16; 10]) -> (f32, f32) {
     (p1_score, p2_score)
 }
 
+/// battle_n is battle for any number of players at once. Each castle goes to whoever sends
+/// the most troops to it, and if several players tie for the most, they split its points
+/// evenly. Returns each player's points, in the same order as `players`.
+pub fn battle_n(players: &[[i16; N_CASTLES]]) -> Vec<f32> {
+    let (points, denominator) = battle_n_exact(players);
+    points
+        .iter()
+        .map(|&p| (p as f64 / denominator as f64) as f32)
+        .collect()
+}
+
+/// battle_n_exact is the same as battle_n, but exact. A castle split `n` ways gives
+/// fractions that floats can't add up exactly, so instead, each player's points are
+/// returned as a numerator over a denominator shared by every player, the least common
+/// multiple of the ways the castles were split. Equal scores then compare equal.
+pub fn battle_n_exact(players: &[[i16; N_CASTLES]]) -> (Vec<u128>, u128) {
+    let most: [i16; N_CASTLES] =
+        std::array::from_fn(|c| players.iter().map(|p| p[c]).max().unwrap_or(0));
+    let n_most: [u128; N_CASTLES] =
+        std::array::from_fn(|c| players.iter().filter(|p| p[c] == most[c]).count().max(1) as u128);
+    let denominator = n_most.iter().fold(1, |acc, &n| acc / gcd(acc, n) * n);
+
+    let mut points = vec![0_u128; players.len()];
+    for castle_num in 0..N_CASTLES {
+        let share = (castle_num + 1) as u128 * (denominator / n_most[castle_num]);
+        for (player, player_points) in players.iter().zip(&mut points) {
+            if player[castle_num] == most[castle_num] {
+                *player_points += share;
+            }
+        }
+    }
+    (points, denominator)
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 /// generate_uniform_random_distribution will create 10 numbers, between 0.0 and 100.0,
 /// which sum to 100.0.
 pub fn generate_uniform_random_distribution() -> [i16; 10] {
     split_points_to_array(&gen_uniform_random_split_points())
 }
 
+/// generate_uniform_random_distribution_with_rng is the same as
+/// generate_uniform_random_distribution, but draws from `rng`, so that seeded runs can
+/// be reproduced.
+pub fn generate_uniform_random_distribution_with_rng<R: Rng>(rng: &mut R) -> [i16; 10] {
+    split_points_to_array(&gen_uniform_random_split_points_with_rng(rng))
+}
+
 pub fn gen_uniform_random_split_points() -> [i16; 9] {
-    // To ensure they sum to 100.0, first generate 9 numbers between 0.0 and 100.0.
+    gen_uniform_random_split_points_with_rng(&mut rand::thread_rng())
+}
+
+pub fn gen_uniform_random_split_points_with_rng<R: Rng>(rng: &mut R) -> [i16; 9] {
+    gen_split_points_with_budget(N_TROOPS, rng)
+}
+
+/// gen_split_points_with_budget is the same as gen_uniform_random_split_points_with_rng,
+/// but for `budget` troops instead of `N_TROOPS`. With fixed-point allocations, the
+/// budget is in the smallest fraction of a troop, like tenths.
+pub fn gen_split_points_with_budget<R: Rng>(budget: i16, rng: &mut R) -> [i16; 9] {
+    // To ensure they sum to the budget, first generate 9 numbers between 0 and the budget.
     // These will be the "splitting points", and the difference between all of them will
     // be the number of troops to send to that castle.
-    let mut rng = rand::thread_rng();
     let mut split_points = [0_i16; 9];
 
-    // Fill the array with random numbers between 0.0 and 100.0.
+    // Fill the array with random numbers between 0 and the budget.
     for sp in &mut split_points {
-        *sp = rng.gen_range(0..=100);
+        *sp = rng.gen_range(0..=budget);
     }
 
     // Sort the split_points, so that the numbers are in ascending order.
     split_points.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    // Round all of the numbers to 1 decimal place
-    // for i in 0..split_points.len() {
-    //     split_points[i] = split_points[i].trunc() + (split_points[i].fract() * 10.0).trunc() / 10.0;
-    // }
-
     split_points
 }
 
 /// split_points_to_array takes a [i16; 9] array of split points, and converts it to a
 /// [i16; 10] array of the distances between the split points.
 pub fn split_points_to_array(split_points: &[i16; 9]) -> [i16; 10] {
+    split_points_to_array_with_budget(split_points, N_TROOPS)
+}
+
+/// split_points_to_array_with_budget is the same as split_points_to_array, but for
+/// `budget` troops instead of `N_TROOPS`
+pub fn split_points_to_array_with_budget(split_points: &[i16; 9], budget: i16) -> [i16; 10] {
     // Calculate the difference between each number and the one before it. The first
     // number in this array is just the first split point, and the last number is
-    // 100.0 - the last split point.
+    // the budget - the last split point.
     let first_val = split_points[0];
-    let last_val = 100 - split_points[split_points.len() - 1];
+    let last_val = budget - split_points[split_points.len() - 1];
     let middle_vals = split_points.windows(2).map(|w| w[1] - w[0]);
 
     // Put all the values together into an array of length 10
@@ -77,13 +166,19 @@ pub fn split_points_to_array(split_points: &[i16; 9]) -> [i16; 10] {
 
 /// array_to_split_points will take a [i16; 10] array of distances between split points,
 /// and convert it to a [i16; 9] array of split points.
-pub fn _array_to_split_points(distribution: [i16; 10]) -> [i16; 9] {
+pub fn array_to_split_points(distribution: [i16; 10]) -> [i16; 9] {
+    array_to_split_points_with_budget(distribution, N_TROOPS)
+}
+
+/// array_to_split_points_with_budget is the same as array_to_split_points, but for
+/// `budget` troops instead of `N_TROOPS`
+pub fn array_to_split_points_with_budget(distribution: [i16; 10], budget: i16) -> [i16; 9] {
     let mut split_points = [0_i16; 9];
     for (idx, &item) in distribution.iter().enumerate() {
         if idx == 0 {
             split_points[idx] = item;
         } else if idx == 9 {
-            split_points[idx - 1] = 100 - item;
+            split_points[idx - 1] = budget - item;
         } else {
             split_points[idx] = split_points[idx - 1] + item;
         }
@@ -93,16 +188,38 @@ pub fn _array_to_split_points(distribution: [i16; 10]) -> [i16; 9] {
 
 /// generate_random_children will take in a [i16; 10] array and create a set of children
 /// from it, with random mutations, +-`variance_range` per castle
-pub fn _generate_random_children(
+pub fn generate_random_children(
     arr: [i16; 10],
     n_children: usize,
     variance_range: i16,
 ) -> Vec<[i16; 10]> {
-    let mut rng = rand::thread_rng();
+    generate_random_children_with_rng(arr, n_children, variance_range, &mut rand::thread_rng())
+}
+
+/// generate_random_children_with_rng is the same as generate_random_children, but draws
+/// from `rng`, so that seeded runs can be reproduced.
+pub fn generate_random_children_with_rng<R: Rng>(
+    arr: [i16; 10],
+    n_children: usize,
+    variance_range: i16,
+    rng: &mut R,
+) -> Vec<[i16; 10]> {
+    generate_random_children_with_budget(arr, n_children, variance_range, N_TROOPS, rng)
+}
+
+/// generate_random_children_with_budget is the same as generate_random_children_with_rng,
+/// but for allocations of `budget` troops instead of `N_TROOPS`
+pub fn generate_random_children_with_budget<R: Rng>(
+    arr: [i16; 10],
+    n_children: usize,
+    variance_range: i16,
+    budget: i16,
+    rng: &mut R,
+) -> Vec<[i16; 10]> {
     let mut children_splits = Vec::new();
 
     // Get the split points of the parent
-    let split_points = _array_to_split_points(arr);
+    let split_points = array_to_split_points_with_budget(arr, budget);
 
     for _ in 0..n_children {
         let mut child_splits = split_points;
@@ -113,11 +230,11 @@ pub fn _generate_random_children(
                 false => *split_pt - rng.gen_range(0..variance_range),
             };
 
-            // Make sure the new number is between 0.0 and 100.0
+            // Make sure the new number is between 0 and the budget
             if new_num < 0 {
                 *split_pt = 0;
-            } else if new_num > 100 {
-                *split_pt = 100;
+            } else if new_num > budget {
+                *split_pt = budget;
             } else {
                 *split_pt = new_num;
             }
@@ -130,10 +247,75 @@ pub fn _generate_random_children(
     }
 
     // Convert the children to a [i16; 10] array
-    children_splits.iter().map(split_points_to_array).collect()
+    children_splits
+        .iter()
+        .map(|sp| split_points_to_array_with_budget(sp, budget))
+        .collect()
+}
+
+/// l1_distance is the total number of troops that differ between two allocations, castle
+/// by castle. Moving `n` troops from one castle to another is a distance of `2 * n`.
+pub fn l1_distance(p1: &[i16; 10], p2: &[i16; 10]) -> i16 {
+    p1.iter().zip(p2).map(|(a, b)| (a - b).abs()).sum()
 }
 
-#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// rescale spreads `budget` troops across the castles in the same proportions as
+/// `distribution`, rounding so that they add up exactly, with the leftover troops going
+/// to the castles that lost the most to rounding. An empty distribution is spread evenly.
+pub fn rescale(distribution: &[i16; N_CASTLES], budget: i16) -> [i16; N_CASTLES] {
+    let budget = budget.max(0);
+    let total: i32 = distribution.iter().map(|&t| t.max(0) as i32).sum();
+    if total == 0 {
+        return rescale(&[1; N_CASTLES], budget);
+    }
+    let exact: Vec<i32> = distribution
+        .iter()
+        .map(|&t| t.max(0) as i32 * budget as i32)
+        .collect();
+    let mut result: [i16; N_CASTLES] = std::array::from_fn(|c| (exact[c] / total) as i16);
+    let mut leftover = budget - result.iter().sum::<i16>();
+    let mut by_remainder: Vec<usize> = (0..N_CASTLES).collect();
+    by_remainder.sort_by_key(|&c| std::cmp::Reverse(exact[c] % total));
+    for c in by_remainder.into_iter().cycle() {
+        if leftover == 0 {
+            break;
+        }
+        result[c] += 1;
+        leftover -= 1;
+    }
+    result
+}
+
+/// What a player knows before a round of a repeated match
+#[derive(Debug, Clone, Copy)]
+pub struct RoundState<'a> {
+    /// The round about to be played, starting from 0
+    pub round: usize,
+    /// How many troops the player has to spread this round
+    pub budget: i16,
+    /// The player's own allocations in the rounds so far
+    pub own_history: &'a [[i16; N_CASTLES]],
+    /// The opponent's allocations in the rounds so far
+    pub opponent_history: &'a [[i16; N_CASTLES]],
+}
+
+/// A way of picking an allocation for each round of a repeated match, which can react to
+/// what has happened so far. The allocation must spend exactly `state.budget` troops.
+pub trait Policy {
+    fn allocate(&mut self, state: &RoundState) -> [i16; N_CASTLES];
+}
+
+/// A static allocation is a policy that plays the same thing every round, rescaled to the
+/// budget if it changes
+impl Policy for [i16; N_CASTLES] {
+    fn allocate(&mut self, state: &RoundState) -> [i16; N_CASTLES] {
+        rescale(self, state.budget)
+    }
+}
+
+#[derive(
+    Debug, Default, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub struct BattleScore {
     pub wins: u32,
     pub ties: u32,
@@ -152,10 +334,14 @@ impl BattleScore {
 
 /// run_battles_slice takes in a bunch of players, and returns some number of the best
 /// players in ascending order (last place first, winner has highest index)
-pub fn _run_battles_slice(
+pub fn run_battles_slice(
     players: &[[i16; 10]],
     num_to_return: Option<usize>,
 ) -> Vec<([i16; 10], BattleScore)> {
+    let _span = tracing::info_span!("battles", n_players = players.len()).entered();
+    let n = players.len() as u64;
+    metrics::add_battles(n * n.saturating_sub(1) / 2);
+
     // Create a HashMap to store the player's index and their score
     let mut results: Vec<BattleScore> = vec![BattleScore::new(); players.len()];
 
@@ -216,9 +402,29 @@ fn bs_with_1_tie() -> BattleScore {
     bs
 }
 
+/// score_against_field battles `player` against every member of `field`, and counts its
+/// wins, ties and losses
+pub fn score_against_field(player: [i16; 10], field: &[[i16; 10]]) -> BattleScore {
+    metrics::add_battles(field.len() as u64);
+    let mut score = BattleScore::new();
+    for opponent in field {
+        let (p_score, o_score) = battle(player, *opponent);
+        match p_score.total_cmp(&o_score) {
+            Ordering::Greater => score.wins += 1,
+            Ordering::Less => score.losses += 1,
+            Ordering::Equal => score.ties += 1,
+        }
+    }
+    score
+}
+
 /// run_battles_set takes in a bunch of players, and returns some number of the best
 /// players in ascending order (last place first, winner has highest index)
 pub fn run_battles_set(players: &FxHashSet<[i16; 10]>) -> FxHashMap<[i16; 10], BattleScore> {
+    let _span = tracing::info_span!("battles", n_players = players.len()).entered();
+    let n = players.len() as u64;
+    metrics::add_battles(n * n.saturating_sub(1) / 2);
+
     // Create a HashMap to store the player's index and their score
     let mut results: FxHashMap<[i16; 10], BattleScore> = FxHashMap::default();
 
@@ -267,8 +473,6 @@ pub fn run_battles_set(players: &FxHashSet<[i16; 10]>) -> FxHashMap<[i16; 10], B
 mod tests {
     use std::vec;
 
-    use test::Bencher;
-
     use super::*;
 
     #[test]
@@ -279,12 +483,128 @@ mod tests {
         for _ in 0..10000 {
             let split_points = gen_uniform_random_split_points();
             let distances = split_points_to_array(&split_points);
-            let split_points_back = _array_to_split_points(distances);
+            let split_points_back = array_to_split_points(distances);
             // Iterate over split_points and split_points_back, and make sure they are the same, to some level of precision.
             assert_eq!(split_points, split_points_back);
         }
     }
 
+    #[test]
+    fn test_is_valid_distribution() {
+        assert!(is_valid_distribution(&[
+            10, 10, 10, 10, 10, 10, 10, 10, 10, 10
+        ]));
+        assert!(is_valid_distribution(&[100, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!is_valid_distribution(&[
+            10, 10, 10, 10, 10, 10, 10, 10, 10, 9
+        ]));
+        assert!(!is_valid_distribution(&[110, -10, 0, 0, 0, 0, 0, 0, 0, 0]));
+        // Would add up to 100 in i16
+        assert!(!is_valid_distribution(&[
+            32767, 32767, 102, 0, 0, 0, 0, 0, 0, 0
+        ]));
+    }
+
+    #[test]
+    fn test_seeded_distribution_is_reproducible() {
+        use rand::SeedableRng;
+
+        let mut rng1 = rand::rngs::StdRng::seed_from_u64(7);
+        let mut rng2 = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let d1 = generate_uniform_random_distribution_with_rng(&mut rng1);
+            let d2 = generate_uniform_random_distribution_with_rng(&mut rng2);
+            assert_eq!(d1, d2);
+            assert_eq!(100, d1.iter().sum::<i16>());
+        }
+    }
+
+    #[test]
+    fn test_run_battles() {
+        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p2: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let p3: [i16; 10] = [90, 0, 0, 0, 0, 0, 0, 0, 0, 10];
+
+        let players = vec![p1, p2, p3];
+        let got: Vec<[i16; 10]> = run_battles_slice(&players, None)
+            .iter()
+            .map(|(troops, _)| *troops)
+            .collect();
+        let want = vec![p2, p3, p1];
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn test_score_against_field() {
+        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p2: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let p3: [i16; 10] = [0, 10, 10, 10, 10, 10, 10, 10, 10, 20];
+
+        let got = score_against_field(p1, &[p1, p2, p3]);
+        let want = BattleScore {
+            wins: 1,
+            ties: 1,
+            losses: 1,
+        };
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn test_battle_slices() {
+        for _ in 0..100 {
+            let p1 = generate_uniform_random_distribution();
+            let p2 = generate_uniform_random_distribution();
+            assert_eq!(battle(p1, p2), battle_slices(&p1, &p2));
+        }
+        assert_eq!((1.0, 2.0), battle_slices(&[3, 0], &[1, 2]));
+    }
+
+    #[test]
+    fn test_rescale() {
+        let p: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        assert_eq!(p, rescale(&p, 100));
+        assert_eq!([5; 10], rescale(&p, 50));
+        let scaled = rescale(&[0, 0, 0, 0, 0, 0, 0, 0, 50, 50], 75);
+        assert_eq!(75, scaled.iter().sum::<i16>());
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0, 38, 37], scaled);
+        assert_eq!(7, rescale(&[0; 10], 7).iter().sum::<i16>());
+    }
+
+    #[test]
+    fn test_battle_n() {
+        for _ in 0..100 {
+            let p1 = generate_uniform_random_distribution();
+            let p2 = generate_uniform_random_distribution();
+            let (p1_score, p2_score) = battle(p1, p2);
+            assert_eq!(vec![p1_score, p2_score], battle_n(&[p1, p2]));
+        }
+
+        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p2: [i16; 10] = [0, 0, 0, 0, 0, 0, 0, 0, 50, 50];
+        let p3: [i16; 10] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 100];
+        // p1 takes castles 1-8, p2 takes castle 9, and p3 takes castle 10
+        assert_eq!(vec![36.0, 9.0, 10.0], battle_n(&[p1, p2, p3]));
+        // Three way ties split the points three ways
+        assert!(battle_n(&[p1, p1, p1])
+            .iter()
+            .all(|s| (s - 55.0 / 3.0).abs() < 1e-4));
+
+        // Splits of 4 and 3 ways come out exactly equal, in twelfths
+        let p4: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 0, 20];
+        let (points, denominator) = battle_n_exact(&[p1, p1, p1, p4]);
+        assert_eq!(12, denominator);
+        assert_eq!(points[0], points[1]);
+        assert_eq!(55 * 12, points.iter().sum::<u128>());
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod benches {
+    extern crate test;
+
+    use super::*;
+    use test::Bencher;
+
     #[bench]
     fn bench_battle_close(b: &mut Bencher) {
         let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
@@ -318,21 +638,6 @@ mod tests {
         b.iter(|| battle(p1, p2));
     }
 
-    #[test]
-    fn test_run_battles() {
-        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
-        let p2: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-        let p3: [i16; 10] = [90, 0, 0, 0, 0, 0, 0, 0, 0, 10];
-
-        let players = vec![p1, p2, p3];
-        let got: Vec<[i16; 10]> = _run_battles_slice(&players, None)
-            .iter()
-            .map(|(troops, _)| *troops)
-            .collect();
-        let want = vec![p2, p3, p1];
-        assert_eq!(want, got);
-    }
-
     #[bench]
     fn bench_run_battles_slice(b: &mut Bencher) {
         let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
@@ -340,7 +645,7 @@ mod tests {
         let p3: [i16; 10] = [90, 0, 0, 0, 0, 0, 0, 0, 0, 10];
 
         let players = vec![p1, p2, p3];
-        b.iter(|| _run_battles_slice(&players, None));
+        b.iter(|| run_battles_slice(&players, None));
     }
 
     #[bench]
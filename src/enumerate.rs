@@ -0,0 +1,294 @@
+//! Exhaustive enumeration of small variants of the game, like 5 castles and 20 troops,
+//! where every allocation can be played against every other one. The results are exact,
+//! so they can be used to check how well the sampling heuristics do.
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::core;
+use crate::metrics;
+
+/// The most strategies `enumerate` is meant for. The payoff matrix takes `n * n` bytes.
+pub const MAX_STRATEGIES: u64 = 20_000;
+
+/// The most castles `enumerate` is meant for, so that every allocation can be printed
+pub const MAX_CASTLES: u64 = 20;
+
+/// A version of the game with a different number of castles or troops. The castle at
+/// index `i` is still worth `i + 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Variant {
+    pub n_castles: usize,
+    pub n_troops: i16,
+}
+
+impl Variant {
+    /// How many allocations there are: the ways of splitting `n_troops` into `n_castles`
+    /// parts, which is `(n_troops + n_castles - 1) choose (n_castles - 1)`
+    pub fn n_strategies(&self) -> u64 {
+        if self.n_castles == 0 || self.n_troops < 0 {
+            return 0;
+        }
+        let n = self.n_troops as u128 + self.n_castles as u128 - 1;
+        let k = (self.n_castles as u128 - 1).min(self.n_troops as u128);
+        // Multiplying before dividing keeps every step a whole number. Any step past
+        // u64::MAX means the answer is too
+        let mut acc: u128 = 1;
+        for i in 0..k {
+            acc = acc * (n - i) / (i + 1);
+            if acc > u64::MAX as u128 {
+                return u64::MAX;
+            }
+        }
+        acc as u64
+    }
+}
+
+/// next_allocation steps `current` to the next allocation in lexicographic order, and
+/// returns false if it was already the last one. It adds a troop to the rightmost castle
+/// with troops after it, and moves the rest of those troops onto the last castle.
+fn next_allocation(current: &mut [i16]) -> bool {
+    let last = current.len() - 1;
+    // The troops on every castle after `i`
+    let mut after = 0;
+    for i in (0..last).rev() {
+        after += current[i + 1];
+        if after > 0 {
+            current[i] += 1;
+            current[i + 1..].fill(0);
+            current[last] = after - 1;
+            return true;
+        }
+    }
+    false
+}
+
+/// all_allocations lists every allocation of `variant`, in lexicographic order. It steps
+/// from one allocation to the next in place, rather than recursing once per castle, so
+/// variants with many castles can't overflow the stack.
+pub fn all_allocations(variant: Variant) -> Vec<Vec<i16>> {
+    let mut out = Vec::with_capacity(variant.n_strategies() as usize);
+    if variant.n_castles == 0 || variant.n_troops < 0 {
+        return out;
+    }
+
+    // The first allocation puts every troop on the last castle
+    let mut current = vec![0_i16; variant.n_castles];
+    current[variant.n_castles - 1] = variant.n_troops;
+    out.push(current.clone());
+    while next_allocation(&mut current) {
+        out.push(current.clone());
+    }
+    out
+}
+
+/// The result of every strategy against every other one, from the row player's side: 1
+/// for a win, 0 for a tie and -1 for a loss. The game is symmetric, so the matrix is
+/// antisymmetric.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoffMatrix {
+    n: usize,
+    payoffs: Vec<i8>,
+}
+
+impl PayoffMatrix {
+    /// build plays every strategy against every other one, a row at a time in parallel
+    pub fn build(strategies: &[Vec<i16>]) -> Self {
+        let _span = tracing::info_span!("payoff_matrix", n = strategies.len()).entered();
+        let n = strategies.len();
+        metrics::add_battles((n * n) as u64);
+        let payoffs = strategies
+            .par_iter()
+            .flat_map_iter(|row| {
+                strategies.iter().map(move |col| {
+                    let (row_score, col_score) = core::battle_slices(row, col);
+                    match row_score.total_cmp(&col_score) {
+                        std::cmp::Ordering::Greater => 1,
+                        std::cmp::Ordering::Less => -1,
+                        std::cmp::Ordering::Equal => 0,
+                    }
+                })
+            })
+            .collect();
+        PayoffMatrix { n, payoffs }
+    }
+
+    /// from_rows makes a matrix from rows of payoffs
+    pub fn from_rows(rows: &[Vec<i8>]) -> Self {
+        let n = rows.len();
+        assert!(
+            rows.iter().all(|r| r.len() == n),
+            "The matrix must be square"
+        );
+        PayoffMatrix {
+            n,
+            payoffs: rows.concat(),
+        }
+    }
+
+    /// How many strategies there are
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> i8 {
+        self.payoffs[row * self.n + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[i8] {
+        &self.payoffs[row * self.n..(row + 1) * self.n]
+    }
+}
+
+/// How one strategy does against the whole strategy space, itself included
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrategyStats {
+    pub allocation: Vec<i16>,
+    pub wins: u32,
+    pub ties: u32,
+    pub losses: u32,
+    pub win_rate: f64,
+    /// The worst result against any strategy: 1, 0 or -1
+    pub security_level: i8,
+}
+
+/// The pure strategy minimax structure of the game
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnumerationSummary {
+    pub variant: Variant,
+    pub n_strategies: usize,
+    /// Every strategy, best win rate first
+    pub strategies: Vec<StrategyStats>,
+    /// The best security level of any strategy, `max_i min_j A[i][j]`
+    pub maximin: i8,
+    /// `min_j max_i A[i][j]`. The game is symmetric, so this is `-maximin`.
+    pub minimax: i8,
+    /// Strategies that never lose. There is a pure saddle point only if there are some,
+    /// and then `maximin == minimax == 0`.
+    pub unbeaten: Vec<Vec<i16>>,
+}
+
+/// summarize works out each strategy's exact record against the whole space, and the pure
+/// strategy minimax values of the payoff matrix
+pub fn summarize(
+    variant: Variant,
+    strategies: &[Vec<i16>],
+    matrix: &PayoffMatrix,
+) -> EnumerationSummary {
+    let n = matrix.len();
+    let mut stats: Vec<StrategyStats> = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let row = matrix.row(i);
+            let count = |v: i8| row.iter().filter(|&&p| p == v).count() as u32;
+            let (wins, ties, losses) = (count(1), count(0), count(-1));
+            StrategyStats {
+                allocation: strategies[i].clone(),
+                wins,
+                ties,
+                losses,
+                win_rate: wins as f64 / n as f64,
+                security_level: row.iter().copied().min().unwrap_or(0),
+            }
+        })
+        .collect();
+    let maximin = stats.iter().map(|s| s.security_level).max().unwrap_or(0);
+    let minimax = (0..n)
+        .map(|j| (0..n).map(|i| matrix.get(i, j)).max().unwrap_or(0))
+        .min()
+        .unwrap_or(0);
+    let unbeaten = stats
+        .iter()
+        .filter(|s| s.losses == 0)
+        .map(|s| s.allocation.clone())
+        .collect();
+    stats.sort_by(|a, b| b.win_rate.total_cmp(&a.win_rate));
+
+    EnumerationSummary {
+        variant,
+        n_strategies: n,
+        strategies: stats,
+        maximin,
+        minimax,
+        unbeaten,
+    }
+}
+
+/// enumerate lists every strategy of `variant`, builds the full payoff matrix, and
+/// summarizes it
+pub fn enumerate(variant: Variant) -> (Vec<Vec<i16>>, PayoffMatrix, EnumerationSummary) {
+    let _span = tracing::info_span!("enumerate", n_castles = variant.n_castles).entered();
+    let strategies = all_allocations(variant);
+    let matrix = PayoffMatrix::build(&strategies);
+    let summary = summarize(variant, &strategies, &matrix);
+    (strategies, matrix, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_allocations() {
+        let variant = Variant {
+            n_castles: 3,
+            n_troops: 4,
+        };
+        let all = all_allocations(variant);
+        assert_eq!(15, variant.n_strategies());
+        assert_eq!(15, all.len());
+        assert_eq!(vec![0, 0, 4], all[0]);
+        assert_eq!(vec![4, 0, 0], all[14]);
+        assert!(all.iter().all(|p| p.iter().sum::<i16>() == 4));
+        // Strictly increasing, so in order and with no repeats
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        // Many castles don't recurse past the end of the stack
+        let variant = Variant {
+            n_castles: 1_000_000,
+            n_troops: 0,
+        };
+        assert_eq!(1, variant.n_strategies());
+        assert_eq!(1, all_allocations(variant).len());
+
+        let variant = Variant {
+            n_castles: 5,
+            n_troops: 20,
+        };
+        assert_eq!(10_626, variant.n_strategies());
+        let variant = Variant {
+            n_castles: 10,
+            n_troops: 100,
+        };
+        assert_eq!(4_263_421_511_271, variant.n_strategies());
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let variant = Variant {
+            n_castles: 3,
+            n_troops: 6,
+        };
+        let (strategies, matrix, summary) = enumerate(variant);
+        assert_eq!(strategies.len(), matrix.len());
+        for i in 0..matrix.len() {
+            assert_eq!(0, matrix.get(i, i));
+            for j in 0..matrix.len() {
+                assert_eq!(matrix.get(i, j), -matrix.get(j, i));
+            }
+        }
+        assert_eq!(-summary.maximin, summary.minimax);
+        assert!(summary.unbeaten.is_empty() == (summary.maximin < 0));
+        assert!(summary
+            .strategies
+            .windows(2)
+            .all(|w| w[0].win_rate >= w[1].win_rate));
+        let total: u32 = summary.strategies.iter().map(|s| s.wins).sum();
+        let total_losses: u32 = summary.strategies.iter().map(|s| s.losses).sum();
+        assert_eq!(total, total_losses);
+    }
+}
@@ -0,0 +1,259 @@
+//! Exact equilibria of zero-sum matrix games, found by solving them as linear programs
+//! with the simplex method. For restricted sets of strategies, like a small enumerated
+//! variant or a pruned pool, this gives the optimal mixed strategies the heuristics can be
+//! checked against.
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::enumerate::PayoffMatrix;
+
+/// The most strategies `solve` is meant for. The simplex tableau takes about
+/// `16 * n * n` bytes.
+pub const MAX_LP_STRATEGIES: usize = 2_000;
+
+/// Anything within this of zero is treated as zero
+const EPSILON: f64 = 1e-9;
+
+/// After this many pivots in a row that do not improve the objective, switch to Bland's
+/// rule, which cannot cycle
+const MAX_DEGENERATE_PIVOTS: usize = 50;
+
+/// The optimal mixed strategies of a zero-sum game, and its value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Equilibrium {
+    /// The expected payoff to the row player when both play optimally
+    pub value: f64,
+    /// The probability the row player plays each row
+    pub row_strategy: Vec<f64>,
+    /// The probability the column player plays each column
+    pub column_strategy: Vec<f64>,
+}
+
+impl Equilibrium {
+    /// The rows that are played with a positive probability
+    pub fn row_support(&self) -> Vec<usize> {
+        support(&self.row_strategy)
+    }
+
+    /// The columns that are played with a positive probability
+    pub fn column_support(&self) -> Vec<usize> {
+        support(&self.column_strategy)
+    }
+}
+
+fn support(strategy: &[f64]) -> Vec<usize> {
+    (0..strategy.len())
+        .filter(|&i| strategy[i] > EPSILON)
+        .collect()
+}
+
+/// Clamps rounding errors below zero, and makes the probabilities add up to 1
+fn normalize(strategy: &mut [f64]) {
+    strategy.iter_mut().for_each(|p| *p = p.max(0.0));
+    let total: f64 = strategy.iter().sum();
+    if total > 0.0 {
+        strategy.iter_mut().for_each(|p| *p /= total);
+    }
+}
+
+/// solve finds an equilibrium of the zero-sum game where the row player gets
+/// `payoffs[i][j]` and the column player loses it. Every row must be the same length.
+///
+/// The payoffs are shifted to all be at least 1, so the value is positive, and then the
+/// column player's problem, maximise `sum(y)` subject to `A y <= 1` and `y >= 0`, is
+/// solved with a dense simplex tableau. The column strategy is `y / sum(y)`, and the row
+/// strategy comes from the duals of the constraints.
+pub fn solve(payoffs: &[Vec<f64>]) -> Equilibrium {
+    let m = payoffs.len();
+    let n = payoffs.first().map_or(0, |r| r.len());
+    assert!(m > 0 && n > 0, "The game needs at least one row and column");
+    assert!(
+        payoffs.iter().all(|r| r.len() == n),
+        "Every row must be the same length"
+    );
+    let _span = tracing::info_span!("equilibrium", n_rows = m, n_cols = n).entered();
+
+    let min_payoff = payoffs
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let shift = 1.0 - min_payoff;
+
+    // Rows 0..m are the constraints, and row m is the objective. Columns 0..n are the
+    // column player's strategies, n..n + m the slack variables, and the last one the
+    // right hand side.
+    let width = n + m + 1;
+    let mut tableau = vec![0.0; (m + 1) * width];
+    for (i, row) in payoffs.iter().enumerate() {
+        let t = &mut tableau[i * width..(i + 1) * width];
+        for (cell, payoff) in t.iter_mut().zip(row) {
+            *cell = payoff + shift;
+        }
+        t[n + i] = 1.0;
+        t[width - 1] = 1.0;
+    }
+    tableau[m * width..m * width + n].fill(-1.0);
+    let mut basis: Vec<usize> = (n..n + m).collect();
+
+    let mut degenerate_pivots = 0;
+    loop {
+        let objective = &tableau[m * width..(m + 1) * width - 1];
+        let entering = if degenerate_pivots < MAX_DEGENERATE_PIVOTS {
+            // Dantzig's rule: the most negative reduced cost
+            objective
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| c < -EPSILON)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(j, _)| j)
+        } else {
+            // Bland's rule: the first negative reduced cost
+            objective.iter().position(|&c| c < -EPSILON)
+        };
+        let Some(entering) = entering else {
+            break;
+        };
+
+        // The ratio test, breaking ties by the smallest basic variable for Bland's rule
+        let leaving = (0..m)
+            .filter(|&i| tableau[i * width + entering] > EPSILON)
+            .min_by(|&a, &b| {
+                let ratio =
+                    |i: usize| tableau[i * width + width - 1] / tableau[i * width + entering];
+                ratio(a).total_cmp(&ratio(b)).then(basis[a].cmp(&basis[b]))
+            })
+            .expect("The shifted game is bounded, so some row limits the entering column");
+
+        let before = tableau[(m + 1) * width - 1];
+        pivot(&mut tableau, width, leaving, entering);
+        basis[leaving] = entering;
+        if tableau[(m + 1) * width - 1] > before + EPSILON {
+            degenerate_pivots = 0;
+        } else {
+            degenerate_pivots += 1;
+        }
+    }
+
+    let total = tableau[(m + 1) * width - 1];
+    let mut column_strategy = vec![0.0; n];
+    for (i, &var) in basis.iter().enumerate() {
+        if var < n {
+            column_strategy[var] = tableau[i * width + width - 1];
+        }
+    }
+    let mut row_strategy = tableau[m * width + n..m * width + n + m].to_vec();
+    normalize(&mut column_strategy);
+    normalize(&mut row_strategy);
+
+    Equilibrium {
+        value: 1.0 / total - shift,
+        row_strategy,
+        column_strategy,
+    }
+}
+
+/// Makes column `col` basic in row `row`, updating the other rows in parallel
+fn pivot(tableau: &mut [f64], width: usize, row: usize, col: usize) {
+    let pivot_value = tableau[row * width + col];
+    tableau[row * width..(row + 1) * width]
+        .iter_mut()
+        .for_each(|x| *x /= pivot_value);
+    let pivot_row = tableau[row * width..(row + 1) * width].to_vec();
+    tableau
+        .par_chunks_mut(width)
+        .enumerate()
+        .filter(|(i, _)| *i != row)
+        .for_each(|(_, r)| {
+            let factor = r[col];
+            if factor.abs() > EPSILON {
+                r.iter_mut()
+                    .zip(&pivot_row)
+                    .for_each(|(x, p)| *x -= factor * p);
+            }
+        });
+}
+
+/// solve_payoff_matrix finds an equilibrium of the game between the strategies of a
+/// `PayoffMatrix`, where a win is worth 1, a tie 0, and a loss -1. The game is
+/// symmetric, so its value is 0, and either side's strategy is optimal for both.
+pub fn solve_payoff_matrix(matrix: &PayoffMatrix) -> Equilibrium {
+    let rows: Vec<Vec<f64>> = (0..matrix.len())
+        .map(|i| matrix.row(i).iter().map(|&p| f64::from(p)).collect())
+        .collect();
+    solve(&rows)
+}
+
+/// The payoff `row_strategy` guarantees against any pure column, and the payoff
+/// `column_strategy` holds every pure row to. At an equilibrium both are the value.
+pub fn guarantees(payoffs: &[Vec<f64>], eq: &Equilibrium) -> (f64, f64) {
+    let n = eq.column_strategy.len();
+    let row_guarantee = (0..n)
+        .map(|j| {
+            payoffs
+                .iter()
+                .zip(&eq.row_strategy)
+                .map(|(r, p)| p * r[j])
+                .sum::<f64>()
+        })
+        .fold(f64::INFINITY, f64::min);
+    let column_guarantee = payoffs
+        .iter()
+        .map(|r| {
+            r.iter()
+                .zip(&eq.column_strategy)
+                .map(|(a, q)| a * q)
+                .sum::<f64>()
+        })
+        .fold(f64::NEG_INFINITY, f64::max);
+    (row_guarantee, column_guarantee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enumerate::{self, Variant};
+
+    #[test]
+    fn test_solve() {
+        // Rock, paper, scissors
+        let rps = vec![
+            vec![0.0, -1.0, 1.0],
+            vec![1.0, 0.0, -1.0],
+            vec![-1.0, 1.0, 0.0],
+        ];
+        let eq = solve(&rps);
+        assert!(eq.value.abs() < 1e-9);
+        for p in eq.row_strategy.iter().chain(&eq.column_strategy) {
+            assert!((p - 1.0 / 3.0).abs() < 1e-9);
+        }
+
+        // Not square, not symmetric, and with a dominated row: the row player should
+        // play the first two rows 2/5 and 3/5 of the time, for a value of 1/5
+        let game = vec![vec![2.0, -1.0], vec![-1.0, 1.0], vec![-2.0, -2.0]];
+        let eq = solve(&game);
+        assert!((eq.value - 0.2).abs() < 1e-9);
+        assert_eq!(vec![0, 1], eq.row_support());
+        assert!((eq.row_strategy[0] - 0.4).abs() < 1e-9);
+        assert!((eq.column_strategy[0] - 0.4).abs() < 1e-9);
+        let (row, column) = guarantees(&game, &eq);
+        assert!((row - 0.2).abs() < 1e-9 && (column - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_solve_payoff_matrix() {
+        let variant = Variant {
+            n_castles: 3,
+            n_troops: 6,
+        };
+        let (_, matrix, _) = enumerate::enumerate(variant);
+        let eq = solve_payoff_matrix(&matrix);
+        assert!(eq.value.abs() < 1e-9);
+        let rows: Vec<Vec<f64>> = (0..matrix.len())
+            .map(|i| matrix.row(i).iter().map(|&p| f64::from(p)).collect())
+            .collect();
+        let (row, column) = guarantees(&rows, &eq);
+        assert!(row > -1e-9 && column < 1e-9);
+    }
+}
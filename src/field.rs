@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::N_CASTLES;
+use crate::fixed_point::Precision;
+
+/// parse_distribution reads an allocation written as `N_CASTLES` whole numbers, separated
+/// by commas and/or whitespace, and optionally wrapped in square brackets. So both
+/// `10, 10, 10, 10, 10, 10, 10, 10, 10, 10` and the `{:?}` output of an array work.
+pub fn parse_distribution(s: &str) -> Result<[i16; N_CASTLES], String> {
+    Precision::WHOLE.parse_distribution(s)
+}
+
+/// parse_field reads one allocation per line. Blank lines, and anything after a `#`, are
+/// ignored.
+pub fn parse_field(s: &str) -> Result<Vec<[i16; N_CASTLES]>, String> {
+    parse_field_with_precision(s, Precision::WHOLE)
+}
+
+/// parse_field_with_precision is the same as `parse_field`, but for allocations in
+/// decimal numbers of troops, which are read in units of `precision`
+pub fn parse_field_with_precision(
+    s: &str,
+    precision: Precision,
+) -> Result<Vec<[i16; N_CASTLES]>, String> {
+    s.lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.split('#').next().unwrap_or_default()))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            precision
+                .parse_distribution(line)
+                .map_err(|e| format!("line {}: {e}", idx + 1))
+        })
+        .collect()
+}
+
+/// read_field reads a file written by `write_field`, or by hand in the format described
+/// in `parse_field`
+pub fn read_field(path: &Path) -> io::Result<Vec<[i16; N_CASTLES]>> {
+    read_field_with_precision(path, Precision::WHOLE)
+}
+
+/// read_field_with_precision is the same as `read_field`, but for allocations in decimal
+/// numbers of troops
+pub fn read_field_with_precision(
+    path: &Path,
+    precision: Precision,
+) -> io::Result<Vec<[i16; N_CASTLES]>> {
+    let contents = fs::read_to_string(path)?;
+    parse_field_with_precision(&contents, precision).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
+}
+
+/// write_field writes one comma separated allocation per line
+pub fn write_field(path: &Path, field: &[[i16; N_CASTLES]]) -> io::Result<()> {
+    write_field_with_precision(path, field, Precision::WHOLE)
+}
+
+/// write_field_with_precision is the same as `write_field`, but writes the allocations
+/// in decimal numbers of troops
+pub fn write_field_with_precision(
+    path: &Path,
+    field: &[[i16; N_CASTLES]],
+    precision: Precision,
+) -> io::Result<()> {
+    let contents: String = field
+        .iter()
+        .map(|p| {
+            let values: Vec<String> = p.iter().map(|&t| precision.format(t)).collect();
+            values.join(",") + "\n"
+        })
+        .collect();
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core;
+
+    #[test]
+    fn test_parse_distribution() {
+        let want: [i16; 10] = [0, 10, 10, 10, 10, 10, 10, 10, 10, 20];
+        assert_eq!(Ok(want), parse_distribution("0,10,10,10,10,10,10,10,10,20"));
+        assert_eq!(Ok(want), parse_distribution(&format!("{want:?}")));
+        assert_eq!(
+            Ok(want),
+            parse_distribution(" 0 10 10 10 10 10 10 10 10 20 ")
+        );
+
+        assert!(parse_distribution("0,10,10").is_err());
+        assert!(parse_distribution("0,10,10,10,10,10,10,10,10,19").is_err());
+        assert!(parse_distribution("0,10,10,10,10,10,10,10,10,2O").is_err());
+    }
+
+    #[test]
+    fn test_parse_field() {
+        let s = "# A field\n10,10,10,10,10,10,10,10,10,10\n\n100,0,0,0,0,0,0,0,0,0 # all in\n";
+        let got = parse_field(s).unwrap();
+        assert_eq!(2, got.len());
+
+        let err = parse_field("10,10,10,10,10,10,10,10,10,10\n1,2,3\n").unwrap_err();
+        assert!(err.starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        let field: Vec<[i16; 10]> = (0..20)
+            .map(|_| core::generate_uniform_random_distribution())
+            .collect();
+        let path = std::env::temp_dir().join(format!(
+            "rs_battle_for_nation_field_{}.txt",
+            std::process::id()
+        ));
+        write_field(&path, &field).unwrap();
+        let got = read_field(&path).unwrap();
+        assert_eq!(field, got);
+
+        // And in tenths of a troop, which whole troops can't read
+        let tenths = Precision::new(1);
+        let field: Vec<[i16; 10]> = (0..20)
+            .map(|_| tenths.generate_uniform_random_distribution(&mut rand::thread_rng()))
+            .collect();
+        write_field_with_precision(&path, &field, tenths).unwrap();
+        let got = read_field_with_precision(&path, tenths).unwrap();
+        assert!(read_field(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(field, got);
+    }
+}
@@ -4,6 +4,8 @@ use itertools::Itertools;
 use rustc_hash::FxHashSet;
 
 use crate::core::{self};
+use crate::metrics;
+use crate::progress::Progress;
 
 /// Run all possible one-on-one matches. A victory is worth 1 battle point, while a tie
 /// is worth 0.5 points. After all the one-on-one matchups are complete, whoever has
@@ -18,6 +20,21 @@ use crate::core::{self};
 ///
 /// Returns a vector of the players, from last place to first place (ascending order)
 pub fn tournament(players: &[[i16; 10]], verbose: bool) -> Vec<[i16; 10]> {
+    run_tournament(players, |round| {
+        if verbose {
+            println!("Finished round {round}");
+        }
+    })
+}
+
+/// The same as `tournament`, but reports each finished elimination round to `progress`
+pub fn tournament_with_progress(players: &[[i16; 10]], progress: &Progress) -> Vec<[i16; 10]> {
+    run_tournament(players, |_| progress.round_finished())
+}
+
+/// Runs the tournament described in `tournament`, calling `on_round` with the round
+/// number after each elimination round
+fn run_tournament(players: &[[i16; 10]], mut on_round: impl FnMut(usize)) -> Vec<[i16; 10]> {
     // If we make this a set, then if there are ever any players with the same troop
     // distribution, they will be combined into one player. Maybe that's fine for our
     // simulation, since they would get the same score in the end.
@@ -34,6 +51,7 @@ pub fn tournament(players: &[[i16; 10]], verbose: bool) -> Vec<[i16; 10]> {
     // it does
     while pl.len() > 2 {
         round += 1;
+        let _span = tracing::debug_span!("elimination_round", round).entered();
         // Run all one on one matches
         let scores = core::run_battles_set(&pl);
 
@@ -84,14 +102,13 @@ pub fn tournament(players: &[[i16; 10]], verbose: bool) -> Vec<[i16; 10]> {
             pl.remove(sorted[0].0);
         }
 
-        if verbose {
-            println!("Finished round {round}");
-        }
+        on_round(round);
     }
 
     // Determine which of the two wins the one-on-oen battle
     let top_two: Vec<[i16; 10]> = pl.iter().copied().collect();
     let (p1_score, p2_score) = core::battle(top_two[0], top_two[1]);
+    metrics::add_battles(1);
     match p1_score.partial_cmp(&p2_score) {
         Some(o) => match o {
             Ordering::Less => {
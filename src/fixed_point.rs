@@ -0,0 +1,242 @@
+//! Fixed-point allocations, for variants that allow fractions of a troop. An allocation
+//! is still a `[i16; N_CASTLES]`, but counted in units of a tenth or a hundredth of a
+//! troop instead of whole troops. Since the units are whole numbers, `core::battle` and
+//! the tournaments work on them unchanged, and ties are exact, with none of the pitfalls
+//! of comparing floats.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{self, N_CASTLES, N_TROOPS};
+
+/// The most decimal places allowed. With 3, the budget would not fit in an `i16`.
+pub const MAX_DECIMALS: u32 = 2;
+
+/// How many decimal places of a troop an allocation is counted in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Precision {
+    decimals: u32,
+}
+
+impl Precision {
+    /// Whole troops, like the original game
+    pub const WHOLE: Precision = Precision { decimals: 0 };
+
+    pub fn new(decimals: u32) -> Self {
+        assert!(
+            decimals <= MAX_DECIMALS,
+            "At most {MAX_DECIMALS} decimal places are supported"
+        );
+        Precision { decimals }
+    }
+
+    pub fn decimals(self) -> u32 {
+        self.decimals
+    }
+
+    /// How many units make up one troop
+    pub fn scale(self) -> i16 {
+        10_i16.pow(self.decimals)
+    }
+
+    /// How many units each player spreads across the castles
+    pub fn budget(self) -> i16 {
+        N_TROOPS * self.scale()
+    }
+
+    /// to_troops converts a number of units to troops, for display
+    pub fn to_troops(self, units: i16) -> f64 {
+        f64::from(units) / f64::from(self.scale())
+    }
+
+    /// format writes a number of units as a decimal, with exactly `decimals` places
+    pub fn format(self, units: i16) -> String {
+        if self.decimals == 0 {
+            return units.to_string();
+        }
+        let scale = self.scale();
+        let sign = if units < 0 { "-" } else { "" };
+        let width = self.decimals as usize;
+        format!(
+            "{sign}{}.{:0width$}",
+            (units / scale).abs(),
+            (units % scale).abs()
+        )
+    }
+
+    /// parse reads a decimal number of troops, like `12.5`, as a whole number of units.
+    /// It is an error to give more decimal places than the precision has, rather than
+    /// rounding them away.
+    pub fn parse(self, s: &str) -> Result<i16, String> {
+        let invalid = || format!("{s:?} is not a number of troops to {self}");
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || fraction.len() > self.decimals as usize
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let whole: i16 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| invalid())?
+        };
+        // Pad the fraction out to the full number of places, so `.5` is 50 hundredths
+        let fraction: i16 = format!("{fraction:0<width$}", width = self.decimals as usize)
+            .parse()
+            .unwrap_or(0);
+        whole
+            .checked_mul(self.scale())
+            .and_then(|w| {
+                if s.starts_with('-') {
+                    w.checked_sub(fraction)
+                } else {
+                    w.checked_add(fraction)
+                }
+            })
+            .ok_or_else(invalid)
+    }
+
+    /// format_distribution writes an allocation in troops, in the same style as the
+    /// `{:?}` output of a whole number allocation
+    pub fn format_distribution(self, distribution: &[i16; N_CASTLES]) -> String {
+        let values: Vec<String> = distribution.iter().map(|&t| self.format(t)).collect();
+        format!("[{}]", values.join(", "))
+    }
+
+    /// parse_distribution is the same as `field::parse_distribution`, but for decimal
+    /// numbers of troops
+    pub fn parse_distribution(self, s: &str) -> Result<[i16; N_CASTLES], String> {
+        let s = s.trim().trim_start_matches('[').trim_end_matches(']');
+        let values: Vec<&str> = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .collect();
+        if values.len() != N_CASTLES {
+            return Err(format!(
+                "Expected {N_CASTLES} castles, got {}",
+                values.len()
+            ));
+        }
+
+        let mut distribution = [0_i16; N_CASTLES];
+        for (troops, value) in distribution.iter_mut().zip(values) {
+            *troops = self.parse(value)?;
+        }
+        if !self.is_valid_distribution(&distribution) {
+            return Err(format!(
+                "Troops must not be negative, and must add up to {N_TROOPS}"
+            ));
+        }
+        Ok(distribution)
+    }
+
+    /// is_valid_distribution is the same as `core::is_valid_distribution`, but for
+    /// allocations in units of this precision
+    pub fn is_valid_distribution(self, distribution: &[i16; N_CASTLES]) -> bool {
+        core::is_valid_distribution_with_budget(distribution, self.budget())
+    }
+
+    /// generate_uniform_random_distribution is the same as
+    /// `core::generate_uniform_random_distribution_with_rng`, but in units of this
+    /// precision
+    pub fn generate_uniform_random_distribution<R: Rng>(self, rng: &mut R) -> [i16; N_CASTLES] {
+        let split_points = core::gen_split_points_with_budget(self.budget(), rng);
+        core::split_points_to_array_with_budget(&split_points, self.budget())
+    }
+
+    /// generate_random_children is the same as `core::generate_random_children_with_rng`,
+    /// but in units of this precision, so `variance_range` is in units too
+    pub fn generate_random_children<R: Rng>(
+        self,
+        arr: [i16; N_CASTLES],
+        n_children: usize,
+        variance_range: i16,
+        rng: &mut R,
+    ) -> Vec<[i16; N_CASTLES]> {
+        core::generate_random_children_with_budget(
+            arr,
+            n_children,
+            variance_range,
+            self.budget(),
+            rng,
+        )
+    }
+}
+
+impl std::fmt::Display for Precision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.decimals {
+            0 => write!(f, "whole troops"),
+            1 => write!(f, "tenths of a troop"),
+            _ => write!(f, "hundredths of a troop"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_and_format() {
+        let tenths = Precision::new(1);
+        assert_eq!(1000, tenths.budget());
+        assert_eq!(Ok(125), tenths.parse("12.5"));
+        assert_eq!(Ok(120), tenths.parse("12"));
+        assert_eq!(Ok(5), tenths.parse(".5"));
+        assert!(tenths.parse("12.55").is_err());
+        assert!(tenths.parse("1e2").is_err());
+        assert!(tenths.parse(".").is_err());
+        assert_eq!("12.5", tenths.format(125));
+        assert_eq!("0.5", tenths.format(5));
+
+        let hundredths = Precision::new(2);
+        assert_eq!(Ok(1205), hundredths.parse("12.05"));
+        assert_eq!(Ok(1250), hundredths.parse("12.5"));
+        assert_eq!("12.05", hundredths.format(1205));
+
+        let s = "[12.5, 0.0, 0.0, 0.0, 0.0, 17.5, 20.0, 20.0, 20.0, 10.0]";
+        let d = tenths.parse_distribution(s).unwrap();
+        assert_eq!([125, 0, 0, 0, 0, 175, 200, 200, 200, 100], d);
+        assert_eq!(s, tenths.format_distribution(&d));
+        assert!(tenths
+            .parse_distribution("12.5, 0, 0, 0, 0, 17.4, 20, 20, 20, 10")
+            .is_err());
+        // Would add up to the budget in i16
+        assert!(Precision::WHOLE
+            .parse_distribution("32767,32767,102,0,0,0,0,0,0,0")
+            .is_err());
+
+        // Whole troops look the same as before
+        let d: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        assert_eq!(format!("{d:?}"), Precision::WHOLE.format_distribution(&d));
+    }
+
+    #[test]
+    fn test_generators() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for decimals in 0..=MAX_DECIMALS {
+            let precision = Precision::new(decimals);
+            for _ in 0..100 {
+                let d = precision.generate_uniform_random_distribution(&mut rng);
+                assert!(precision.is_valid_distribution(&d));
+                for child in precision.generate_random_children(d, 5, 3, &mut rng) {
+                    assert!(precision.is_valid_distribution(&child));
+                }
+            }
+        }
+
+        // Half a troop more at a castle wins it
+        let tenths = Precision::new(1);
+        let p1 = tenths
+            .parse_distribution("10.5, 9.5, 10, 10, 10, 10, 10, 10, 10, 10")
+            .unwrap();
+        let p2 = tenths
+            .parse_distribution("10, 10, 10, 10, 10, 10, 10, 10, 10, 10")
+            .unwrap();
+        assert_eq!((27.0, 28.0), core::battle(p1, p2));
+    }
+}
@@ -0,0 +1,171 @@
+//! A tournament format for more than two players at once. Every round, the players are
+//! shuffled into free-for-all matches of `match_size`, played with `core::battle_n`, and
+//! whoever scores the most points in a match wins it.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::core::{self, N_CASTLES};
+use crate::metrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FreeForAllConfig {
+    /// How many players are in each match
+    pub match_size: usize,
+    pub n_rounds: usize,
+    /// Round `i` is shuffled by an RNG seeded with `seed + i`
+    pub seed: u64,
+}
+
+impl Default for FreeForAllConfig {
+    fn default() -> Self {
+        FreeForAllConfig {
+            match_size: 4,
+            n_rounds: 20,
+            seed: 0,
+        }
+    }
+}
+
+/// How one player did over the whole tournament
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Standing {
+    pub player: [i16; N_CASTLES],
+    /// Matches won, with a match shared by `n` players counting as `1 / n` of a win
+    pub match_wins: f32,
+    /// Total castle points, over every match
+    pub points: f32,
+    pub n_matches: u32,
+}
+
+/// groups splits the shuffled player indexes in `order` into matches of `match_size`. Any
+/// players left over join the last match, so nobody sits a round out, and no match is
+/// smaller than `match_size`.
+fn groups(order: &[usize], match_size: usize) -> Vec<&[usize]> {
+    let match_size = match_size.clamp(2, order.len().max(2));
+    let n_matches = (order.len() / match_size).max(1);
+    (0..n_matches)
+        .map(|m| {
+            let end = if m + 1 == n_matches {
+                order.len()
+            } else {
+                (m + 1) * match_size
+            };
+            &order[m * match_size..end]
+        })
+        .collect()
+}
+
+/// tournament plays `config.n_rounds` rounds of free-for-all matches between `players`.
+/// The players are ranked by match wins, and then by total points.
+///
+/// Returns the standings, from last place to first place (ascending order), like the
+/// other tournament formats.
+pub fn tournament(players: &[[i16; N_CASTLES]], config: &FreeForAllConfig) -> Vec<Standing> {
+    let _span = tracing::info_span!(
+        "free_for_all",
+        n_players = players.len(),
+        match_size = config.match_size
+    )
+    .entered();
+    let mut standings: Vec<Standing> = players
+        .iter()
+        .map(|&player| Standing {
+            player,
+            match_wins: 0.0,
+            points: 0.0,
+            n_matches: 0,
+        })
+        .collect();
+    if players.len() < 2 {
+        return standings;
+    }
+
+    for round in 0..config.n_rounds {
+        let _span = tracing::debug_span!("free_for_all_round", round).entered();
+        let mut order: Vec<usize> = (0..players.len()).collect();
+        order.shuffle(&mut StdRng::seed_from_u64(
+            config.seed.wrapping_add(round as u64),
+        ));
+        let matches = groups(&order, config.match_size);
+        metrics::add_battles(matches.len() as u64);
+
+        // Each match gives its players' points, and their shares of the win
+        let results: Vec<Vec<(usize, f32, f32)>> = matches
+            .par_iter()
+            .map(|idxs| {
+                let match_players: Vec<[i16; N_CASTLES]> =
+                    idxs.iter().map(|&i| players[i]).collect();
+                // Exact points, so that a genuinely shared win is never split up by rounding
+                let (points, denominator) = core::battle_n_exact(&match_players);
+                let best = points.iter().copied().max().unwrap_or(0);
+                let n_best = points.iter().filter(|&&p| p == best).count() as f32;
+                idxs.iter()
+                    .zip(points)
+                    .map(|(&i, p)| {
+                        let share = if p == best { 1.0 / n_best } else { 0.0 };
+                        (i, (p as f64 / denominator as f64) as f32, share)
+                    })
+                    .collect()
+            })
+            .collect();
+        for (i, points, win_share) in results.into_iter().flatten() {
+            standings[i].points += points;
+            standings[i].match_wins += win_share;
+            standings[i].n_matches += 1;
+        }
+    }
+
+    standings.sort_by(|a, b| {
+        a.match_wins
+            .total_cmp(&b.match_wins)
+            .then(a.points.total_cmp(&b.points))
+    });
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let order: Vec<usize> = (0..10).collect();
+        let sizes: Vec<usize> = groups(&order, 4).iter().map(|g| g.len()).collect();
+        assert_eq!(vec![4, 6], sizes);
+        let sizes: Vec<usize> = groups(&order, 5).iter().map(|g| g.len()).collect();
+        assert_eq!(vec![5, 5], sizes);
+        let sizes: Vec<usize> = groups(&order, 20).iter().map(|g| g.len()).collect();
+        assert_eq!(vec![10], sizes);
+    }
+
+    #[test]
+    fn test_tournament() {
+        let strong: [i16; 10] = [0, 0, 0, 0, 0, 0, 0, 30, 35, 35];
+        let mut players = vec![strong];
+        for i in 0..11 {
+            // Spread out, so they lose the top castles to `strong`
+            let mut p = [10_i16; 10];
+            p[i % 9] += 1;
+            p[9] -= 1;
+            players.push(p);
+        }
+        let config = FreeForAllConfig {
+            match_size: 3,
+            n_rounds: 10,
+            seed: 4,
+        };
+        let standings = tournament(&players, &config);
+        assert_eq!(players.len(), standings.len());
+        assert!(standings.iter().all(|s| s.n_matches == 10));
+        let total_wins: f32 = standings.iter().map(|s| s.match_wins).sum();
+        assert!((total_wins - 40.0).abs() < 1e-3);
+        assert_eq!(strong, standings.last().unwrap().player);
+
+        // Seeded runs are the same
+        assert_eq!(standings, tournament(&players, &config));
+    }
+}
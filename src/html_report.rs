@@ -0,0 +1,449 @@
+//! A self-contained HTML report of a run. The charts are inline SVG, built here, so the
+//! file can be opened anywhere without the tool or a network connection.
+
+use std::fmt::Write;
+
+use crate::checkpoint::RunConfig;
+use crate::core::{self, N_CASTLES};
+use crate::fixed_point::Precision;
+use crate::metrics::MetricsSummary;
+
+/// How many of the top finishers are in the head-to-head matrix
+const TOP_N: usize = 20;
+
+/// The finish places are grouped into at most this many columns in the placement chart
+const MAX_PLACEMENT_BINS: usize = 50;
+
+const WIDTH: f64 = 860.0;
+const FIELD_COLOR: &str = "#9e9e9e";
+const WINNERS_COLOR: &str = "#1e88e5";
+
+/// Everything that goes into the report
+pub struct ReportData<'a> {
+    pub config: RunConfig,
+    /// The players the tournaments were run on, or a sample of them
+    pub field: &'a [[i16; N_CASTLES]],
+    /// The winners of the tournaments
+    pub winners: &'a [[i16; N_CASTLES]],
+    /// The winners in the order they finished the final tournament, first place first
+    pub ranking: &'a [[i16; N_CASTLES]],
+    pub metrics: Option<&'a MetricsSummary>,
+}
+
+/// The minimum, lower quartile, median, upper quartile and maximum of `values`
+fn five_numbers(mut values: Vec<i16>) -> [f64; 5] {
+    if values.is_empty() {
+        return [0.0; 5];
+    }
+    values.sort_unstable();
+    let quantile = |q: f64| {
+        let pos = q * (values.len() - 1) as f64;
+        let (low, high) = (pos.floor() as usize, pos.ceil() as usize);
+        let frac = pos - low as f64;
+        values[low] as f64 * (1.0 - frac) + values[high] as f64 * frac
+    };
+    [
+        quantile(0.0),
+        quantile(0.25),
+        quantile(0.5),
+        quantile(0.75),
+        quantile(1.0),
+    ]
+}
+
+/// A color from white to blue, for `t` between 0 and 1
+fn sequential_color(t: f64) -> String {
+    let t = t.clamp(0.0, 1.0);
+    let lerp = |from: f64, to: f64| (from + (to - from) * t).round() as u8;
+    format!(
+        "rgb({},{},{})",
+        lerp(255.0, 13.0),
+        lerp(255.0, 71.0),
+        lerp(255.0, 161.0)
+    )
+}
+
+/// A color from red, through white, to green, for `t` between -1 and 1
+fn diverging_color(t: f64) -> String {
+    let t = t.clamp(-1.0, 1.0);
+    let (r, g, b) = if t < 0.0 {
+        (211.0, 47.0, 47.0)
+    } else {
+        (56.0, 142.0, 60.0)
+    };
+    let t = t.abs();
+    let lerp = |to: f64| (255.0 + (to - 255.0) * t).round() as u8;
+    format!("rgb({},{},{})", lerp(r), lerp(g), lerp(b))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// box_plots draws, for each castle, a box plot of the troops the field sends to it next
+/// to one for the winners. The whiskers go out to the minimum and maximum. The axis is in
+/// troops, whatever `precision` the allocations are counted in.
+pub fn box_plots(
+    field: &[[i16; N_CASTLES]],
+    winners: &[[i16; N_CASTLES]],
+    precision: Precision,
+) -> String {
+    let height = 360.0;
+    let (left, right, top, bottom) = (50.0, 10.0, 20.0, 40.0);
+    let plot_height = height - top - bottom;
+    let group_width = (WIDTH - left - right) / N_CASTLES as f64;
+    let box_width = group_width * 0.3;
+
+    let series = [
+        (field, FIELD_COLOR, "field"),
+        (winners, WINNERS_COLOR, "winners"),
+    ];
+    // stats[castle][series]
+    let stats: Vec<Vec<[f64; 5]>> = (0..N_CASTLES)
+        .map(|c| {
+            series
+                .iter()
+                .map(|(players, _, _)| {
+                    five_numbers(players.iter().map(|p| p[c]).collect())
+                        .map(|units| units / f64::from(precision.scale()))
+                })
+                .collect()
+        })
+        .collect();
+    let max = stats.iter().flatten().map(|s| s[4]).fold(10.0, f64::max);
+    let max = (max / 10.0).ceil() * 10.0;
+    let y = |troops: f64| top + plot_height * (1.0 - troops / max);
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" font-family="sans-serif" font-size="12">"#
+    )
+    .unwrap();
+    let step = if max > 60.0 { 20.0 } else { 10.0 };
+    let mut tick = 0.0;
+    while tick <= max {
+        write!(
+            svg,
+            r##"<line x1="{left}" x2="{}" y1="{y}" y2="{y}" stroke="#eee"/><text x="{}" y="{}" text-anchor="end">{tick}</text>"##,
+            WIDTH - right,
+            left - 6.0,
+            y(tick) + 4.0,
+            y = y(tick)
+        )
+        .unwrap();
+        tick += step;
+    }
+    for (c, castle_stats) in stats.iter().enumerate() {
+        let center = left + group_width * (c as f64 + 0.5);
+        write!(
+            svg,
+            r#"<text x="{center}" y="{}" text-anchor="middle">Castle {}</text>"#,
+            height - bottom + 18.0,
+            c + 1
+        )
+        .unwrap();
+        for (s, ((_, color, name), &[min, q1, median, q3, max])) in
+            series.iter().zip(castle_stats).enumerate()
+        {
+            let x = center + (s as f64 - 1.0) * box_width - 2.0 + 4.0 * s as f64;
+            let mid = x + box_width / 2.0;
+            write!(
+                svg,
+                r##"<g><title>Castle {castle} {name}: min {min}, quartiles {q1} / {median} / {q3}, max {max}</title><line x1="{mid}" x2="{mid}" y1="{}" y2="{}" stroke="{color}"/><rect x="{x}" y="{}" width="{box_width}" height="{}" fill="{color}" fill-opacity="0.35" stroke="{color}"/><line x1="{x}" x2="{}" y1="{}" y2="{}" stroke="{color}" stroke-width="2"/></g>"##,
+                y(max),
+                y(min),
+                y(q3),
+                (y(q1) - y(q3)).max(1.0),
+                x + box_width,
+                y(median),
+                y(median),
+                castle = c + 1,
+            )
+            .unwrap();
+        }
+    }
+    for (s, (_, color, name)) in series.iter().enumerate() {
+        let x = left + 10.0 + 90.0 * s as f64;
+        write!(
+            svg,
+            r#"<rect x="{x}" y="{}" width="12" height="12" fill="{color}" fill-opacity="0.5"/><text x="{}" y="{}">{name}</text>"#,
+            height - 16.0,
+            x + 16.0,
+            height - 6.0
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// placement_chart groups the finishers into columns by finish place, first place on the
+/// left, and shades each castle by how many troops that group sends to it on average
+pub fn placement_chart(ranking: &[[i16; N_CASTLES]], precision: Precision) -> String {
+    let n_bins = ranking.len().clamp(1, MAX_PLACEMENT_BINS);
+    let (left, top, bottom) = (70.0, 10.0, 40.0);
+    let cell_height = 24.0;
+    let height = top + bottom + cell_height * N_CASTLES as f64;
+    let cell_width = (WIDTH - left - 10.0) / n_bins as f64;
+
+    let means: Vec<[f64; N_CASTLES]> = (0..n_bins)
+        .map(|bin| {
+            let start = bin * ranking.len() / n_bins;
+            let end = ((bin + 1) * ranking.len() / n_bins)
+                .max(start + 1)
+                .min(ranking.len());
+            let members = &ranking[start.min(end)..end];
+            let mut mean = [0.0; N_CASTLES];
+            for p in members {
+                for (m, &t) in mean.iter_mut().zip(p) {
+                    *m += precision.to_troops(t) / members.len() as f64;
+                }
+            }
+            mean
+        })
+        .collect();
+    let max = means.iter().flatten().cloned().fold(1.0, f64::max);
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" font-family="sans-serif" font-size="12">"#
+    )
+    .unwrap();
+    for c in 0..N_CASTLES {
+        // Castle 10 at the top
+        let y = top + cell_height * (N_CASTLES - 1 - c) as f64;
+        write!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">Castle {}</text>"#,
+            left - 6.0,
+            y + cell_height / 2.0 + 4.0,
+            c + 1
+        )
+        .unwrap();
+        for (bin, mean) in means.iter().enumerate() {
+            write!(
+                svg,
+                r#"<rect x="{}" y="{y}" width="{}" height="{cell_height}" fill="{}"><title>Places {}-{}, castle {}: {:.1} troops</title></rect>"#,
+                left + cell_width * bin as f64,
+                cell_width + 0.5,
+                sequential_color(mean[c] / max),
+                bin * ranking.len() / n_bins + 1,
+                ((bin + 1) * ranking.len() / n_bins).max(1),
+                c + 1,
+                mean[c]
+            )
+            .unwrap();
+        }
+    }
+    write!(
+        svg,
+        r#"<text x="{left}" y="{}">1st place</text><text x="{}" y="{}" text-anchor="end">last place ({})</text>"#,
+        height - bottom + 18.0,
+        WIDTH - 10.0,
+        height - bottom + 18.0,
+        ranking.len()
+    )
+    .unwrap();
+    svg.push_str("</svg>");
+    svg
+}
+
+/// head_to_head draws a matrix of how each of `players` does against each other one,
+/// shaded by the points margin of the row player over the column player
+pub fn head_to_head(players: &[[i16; N_CASTLES]], precision: Precision) -> String {
+    let n = players.len();
+    let cell = 30.0;
+    let (left, top) = (40.0, 30.0);
+    let size = left + cell * n as f64 + 10.0;
+    // Every point there is to win, which is the largest possible margin
+    let total_points = (N_CASTLES * (N_CASTLES + 1) / 2) as f64;
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{}" font-family="sans-serif" font-size="11">"#,
+        top + cell * n as f64 + 10.0
+    )
+    .unwrap();
+    for i in 0..n {
+        write!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">{}</text><text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            left - 6.0,
+            top + cell * i as f64 + cell / 2.0 + 4.0,
+            i + 1,
+            left + cell * i as f64 + cell / 2.0,
+            top - 8.0,
+            i + 1
+        )
+        .unwrap();
+        for j in 0..n {
+            let (x, y) = (left + cell * j as f64, top + cell * i as f64);
+            if i == j {
+                write!(
+                    svg,
+                    r##"<rect x="{x}" y="{y}" width="{cell}" height="{cell}" fill="#f5f5f5"/>"##
+                )
+                .unwrap();
+                continue;
+            }
+            let (p1, p2) = core::battle(players[i], players[j]);
+            let margin = (p1 - p2) as f64;
+            write!(
+                svg,
+                r##"<rect x="{x}" y="{y}" width="{cell}" height="{cell}" fill="{}" stroke="#fff"><title>#{} {} vs #{} {}: {p1} to {p2}</title></rect>"##,
+                diverging_color(margin / total_points * 2.0),
+                i + 1,
+                precision.format_distribution(&players[i]),
+                j + 1,
+                precision.format_distribution(&players[j])
+            )
+            .unwrap();
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn metadata_table(data: &ReportData) -> String {
+    let mut rows: Vec<(String, String)> = vec![
+        ("Version".into(), env!("CARGO_PKG_VERSION").into()),
+        ("Seed".into(), data.config.seed.to_string()),
+        (
+            "Tournament size".into(),
+            data.config.tournament_size.to_string(),
+        ),
+        ("Tournaments".into(), data.config.n_tournaments.to_string()),
+        (
+            "Troops counted in".into(),
+            data.config.precision().to_string(),
+        ),
+        ("Winners".into(), data.winners.len().to_string()),
+        ("Field shown".into(), data.field.len().to_string()),
+    ];
+    if let Some(first) = data.ranking.first() {
+        rows.push((
+            "Final winner".into(),
+            data.config.precision().format_distribution(first),
+        ));
+    }
+    if let Some(metrics) = data.metrics {
+        rows.push((
+            "Wall time".into(),
+            format!("{:.1}s", metrics.wall_time_secs),
+        ));
+        rows.push(("Battles".into(), metrics.battles.to_string()));
+        rows.push((
+            "Battles per second".into(),
+            format!("{:.0}", metrics.battles_per_sec),
+        ));
+    }
+    let mut html = String::from("<table>");
+    for (key, value) in rows {
+        write!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(&key),
+            escape(&value)
+        )
+        .unwrap();
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// render builds the whole report as one HTML page
+pub fn render(data: &ReportData) -> String {
+    let _span = tracing::info_span!("html_report").entered();
+    let top: Vec<[i16; N_CASTLES]> = data.ranking.iter().take(TOP_N).copied().collect();
+    let mut top_table = String::from("<table><tr><th>Place</th><th>Allocation</th></tr>");
+    for (idx, p) in top.iter().enumerate() {
+        write!(
+            top_table,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            idx + 1,
+            data.config.precision().format_distribution(p)
+        )
+        .unwrap();
+    }
+    top_table.push_str("</table>");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Battle for Riddler Nation report</title>
+<style>
+body {{ font-family: sans-serif; max-width: 900px; margin: 2em auto; color: #222; }}
+table {{ border-collapse: collapse; margin: 1em 0; }}
+th, td {{ text-align: left; padding: 2px 12px 2px 0; font-variant-numeric: tabular-nums; }}
+h2 {{ margin-top: 2em; }}
+</style>
+</head>
+<body>
+<h1>Battle for Riddler Nation report</h1>
+<h2>Run</h2>
+{metadata}
+<h2>Troops per castle</h2>
+<p>How many troops the field and the tournament winners send to each castle. Boxes span the middle half, the thick line is the median, and the whiskers go out to the minimum and maximum.</p>
+{box_plots}
+<h2>Finish place and allocation</h2>
+<p>The winners in the order they finished the final tournament, grouped by place. Darker cells mean more troops, on average.</p>
+{placement}
+<h2>Top {n_top} head to head</h2>
+<p>Green when the row player beats the column player, red when it loses, shaded by the points margin. Hover over a cell for the details.</p>
+{head_to_head}
+{top_table}
+</body>
+</html>
+"#,
+        metadata = metadata_table(data),
+        box_plots = box_plots(data.field, data.winners, data.config.precision()),
+        placement = placement_chart(data.ranking, data.config.precision()),
+        n_top = top.len(),
+        head_to_head = head_to_head(&top, data.config.precision()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_five_numbers() {
+        assert_eq!([1.0, 2.0, 3.0, 4.0, 5.0], five_numbers(vec![5, 3, 1, 4, 2]));
+        assert_eq!([0.0, 0.75, 1.5, 2.25, 3.0], five_numbers(vec![0, 1, 2, 3]));
+        assert_eq!([0.0; 5], five_numbers(Vec::new()));
+    }
+
+    #[test]
+    fn test_render() {
+        let players: Vec<[i16; 10]> = (0..30)
+            .map(|_| core::generate_uniform_random_distribution())
+            .collect();
+        let data = ReportData {
+            config: RunConfig {
+                tournament_size: 10,
+                n_tournaments: 3,
+                seed: 7,
+                decimals: 0,
+            },
+            field: &players,
+            winners: &players[..25],
+            ranking: &players[..25],
+            metrics: None,
+        };
+        let html = render(&data);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(3, html.matches("<svg").count());
+        assert_eq!(html.matches("<svg").count(), html.matches("</svg>").count());
+        // The head to head matrix only has the top 20, and no cell for playing yourself
+        assert_eq!(20 * 19, html.matches(" vs #").count());
+    }
+}
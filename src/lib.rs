@@ -0,0 +1,67 @@
+// The benchmarks use the unstable `test` crate, so they are only built on nightly, with
+// `cargo +nightly bench --features bench`
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
+//! An engine for the Riddler's Battle for Riddler Nation, a Colonel Blotto game. Each
+//! player spreads `core::N_TROOPS` troops across `core::N_CASTLES` castles, and whoever
+//! sends more troops to a castle wins its points.
+//!
+//! - `core` has the game itself: `battle`, the random strategy generators, round robin
+//!   scoring, and the `Policy` trait that `repeated` plays over several rounds.
+//!   `fixed_point` has allocations in fractions of a troop, `asymmetric` has games where
+//!   the sides have different budgets and castle values, `noise` has battles where the
+//!   troops that arrive are not quite the ones that were sent, and `report` breaks a
+//!   single battle down castle by castle
+//! - `final_battle` and `seventh_battle` have the tournament formats, `free_for_all`
+//!   has matches of more than two players at once with `core::battle_n`, `enumerate`
+//!   solves small variants of the game exactly, `pruning` shrinks sets of candidate
+//!   strategies before either, and `equilibrium` finds exact optimal mixed strategies
+//!   with linear programming
+//! - `bootstrap`, `checkpoint`, `metrics` and `progress` support long tournament runs, and
+//!   `html_report` writes up a run as a self-contained HTML page
+//! - `best_response`, `local_search` and `annealing` search for allocations that do well
+//!   against a field, with hill climbing, tabu search and simulated annealing, and
+//!   `coevolution` evolves a population against a hall of fame of its own champions
+//! - `sensitivity` shows how much small changes to an allocation matter, `clustering`
+//!   groups strategies into archetypes, and `field` reads and writes fields as text files
+//!
+//! With the `python` feature, the crate also builds as a Python extension module, with
+//! the `server` feature, `server` has a local HTTP API, and with the `tui` feature, `tui`
+//! has an interactive terminal UI for exploring a field.
+
+pub mod annealing;
+pub mod asymmetric;
+pub mod best_response;
+pub mod bootstrap;
+pub mod checkpoint;
+pub mod clustering;
+pub mod coevolution;
+pub mod core;
+pub mod enumerate;
+pub mod equilibrium;
+pub mod field;
+pub mod final_battle;
+pub mod fixed_point;
+pub mod free_for_all;
+pub mod html_report;
+pub mod local_search;
+pub mod metrics;
+pub mod noise;
+pub mod progress;
+pub mod pruning;
+#[cfg(feature = "python")]
+mod python;
+pub mod repeated;
+pub mod report;
+pub mod sensitivity;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod seventh_battle;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+pub use crate::core::{
+    battle, generate_uniform_random_distribution, generate_uniform_random_distribution_with_rng,
+    BattleScore, N_CASTLES, N_TROOPS,
+};
+pub use crate::final_battle::tournament;
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::best_response::{troop_transfers, victory_points};
+use crate::core::{self, BattleScore, N_CASTLES};
+
+/// How many random troop transfers are made to a starting point to get the start of each
+/// restart after the first
+const KICK_MOVES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Strategy {
+    /// Look at every neighbour, and move to the best one
+    SteepestAscent,
+    /// Look at the neighbours in a random order, and move to the first one that is better
+    FirstImprovement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LocalSearchConfig {
+    pub strategy: Strategy,
+    /// The neighbours of an allocation are the ones made by moving between 1 and this many
+    /// troops from one castle to another
+    pub max_transfer: i16,
+    /// How many of the most recently visited allocations the search may not go back to.
+    /// With 0, the search stops at the first local optimum. Otherwise it keeps moving to
+    /// the best allowed neighbour, even when that is worse.
+    pub tabu_tenure: usize,
+    /// With tabu on, how many steps to go without finding a new best before stopping
+    pub patience: usize,
+    /// How many searches to run. The first starts from the given allocation, and the rest
+    /// from random changes to it.
+    pub n_restarts: usize,
+    /// Restart `i` is seeded with `seed + i`
+    pub seed: u64,
+}
+
+impl Default for LocalSearchConfig {
+    fn default() -> Self {
+        LocalSearchConfig {
+            strategy: Strategy::SteepestAscent,
+            max_transfer: 5,
+            tabu_tenure: 0,
+            patience: 20,
+            n_restarts: 4,
+            seed: 0,
+        }
+    }
+}
+
+/// How good `player` is against `field`: its victory points, and then how many more
+/// points it scored than its opponents in total. The second part gives the search a slope
+/// to follow when no transfer changes a result.
+fn fitness(player: [i16; N_CASTLES], field: &[[i16; N_CASTLES]]) -> (BattleScore, (u32, i32)) {
+    let score = core::score_against_field(player, field);
+    let margin: f32 = field
+        .iter()
+        .map(|&opponent| {
+            let (p_score, o_score) = core::battle(player, opponent);
+            p_score - o_score
+        })
+        .sum();
+    // Scores are all multiples of 0.5, so doubling the margin makes it exact
+    (score, (victory_points(&score), (2.0 * margin) as i32))
+}
+
+/// climb runs a single search from `start`, and returns the best allocation it visited
+pub fn climb<R: Rng>(
+    start: [i16; N_CASTLES],
+    field: &[[i16; N_CASTLES]],
+    config: &LocalSearchConfig,
+    rng: &mut R,
+) -> ([i16; N_CASTLES], BattleScore) {
+    climb_with(start, |p| fitness(p, field), config, rng)
+}
+
+/// climb_with is the same as climb, but scores allocations with `fitness` instead of
+/// against a field, for games other than the symmetric one. `fitness` returns the
+/// allocation's record, and a key to maximise, like victory points and then the margin.
+pub fn climb_with<R, F>(
+    start: [i16; N_CASTLES],
+    fitness: F,
+    config: &LocalSearchConfig,
+    rng: &mut R,
+) -> ([i16; N_CASTLES], BattleScore)
+where
+    R: Rng,
+    F: Fn([i16; N_CASTLES]) -> (BattleScore, (u32, i32)),
+{
+    let mut current = start;
+    let (_, mut current_fitness) = fitness(current);
+    let (mut best, (mut best_score, mut best_fitness)) = (start, fitness(start));
+    let mut tabu: VecDeque<[i16; N_CASTLES]> = VecDeque::with_capacity(config.tabu_tenure + 1);
+    let mut steps_since_best = 0;
+
+    loop {
+        let mut neighbours: Vec<[i16; N_CASTLES]> = troop_transfers(current, config.max_transfer)
+            .filter(|p| !tabu.contains(p))
+            .collect();
+        let next = match config.strategy {
+            Strategy::SteepestAscent => neighbours
+                .into_iter()
+                .map(|p| (p, fitness(p)))
+                .max_by_key(|(_, (_, fit))| *fit),
+            Strategy::FirstImprovement => {
+                neighbours.shuffle(rng);
+                let mut best_neighbour = None;
+                for p in neighbours {
+                    let (score, fit) = fitness(p);
+                    if fit > current_fitness {
+                        best_neighbour = Some((p, (score, fit)));
+                        break;
+                    }
+                    if best_neighbour
+                        .as_ref()
+                        .is_none_or(|(_, (_, best_fit))| fit > *best_fit)
+                    {
+                        best_neighbour = Some((p, (score, fit)));
+                    }
+                }
+                best_neighbour
+            }
+        };
+        let Some((player, (score, fit))) = next else {
+            // Every neighbour is tabu
+            return (best, best_score);
+        };
+        if config.tabu_tenure == 0 && fit <= current_fitness {
+            return (best, best_score);
+        }
+
+        if config.tabu_tenure > 0 {
+            tabu.push_back(current);
+            if tabu.len() > config.tabu_tenure {
+                tabu.pop_front();
+            }
+        }
+        (current, current_fitness) = (player, fit);
+        if fit > best_fitness {
+            (best, best_score, best_fitness) = (player, score, fit);
+            steps_since_best = 0;
+        } else {
+            steps_since_best += 1;
+            if steps_since_best >= config.patience {
+                return (best, best_score);
+            }
+        }
+    }
+}
+
+/// kick makes `KICK_MOVES` random troop transfers to `player`
+fn kick<R: Rng>(mut player: [i16; N_CASTLES], max_transfer: i16, rng: &mut R) -> [i16; N_CASTLES] {
+    for _ in 0..KICK_MOVES {
+        let from = rng.gen_range(0..N_CASTLES);
+        let to = rng.gen_range(0..N_CASTLES);
+        let n = rng.gen_range(0..=player[from].min(max_transfer));
+        player[from] -= n;
+        player[to] += n;
+    }
+    player
+}
+
+/// The result of polishing one allocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Polished {
+    pub start: [i16; N_CASTLES],
+    pub start_score: BattleScore,
+    pub best: [i16; N_CASTLES],
+    pub best_score: BattleScore,
+}
+
+impl Polished {
+    /// How many more victory points the polished allocation gets than the start, doubled
+    /// like `victory_points`
+    pub fn gain(&self) -> i64 {
+        victory_points(&self.best_score) as i64 - victory_points(&self.start_score) as i64
+    }
+}
+
+/// polish runs `config.n_restarts` local searches around `start` against `field`, in
+/// parallel, and keeps the best allocation found
+pub fn polish(
+    start: [i16; N_CASTLES],
+    field: &[[i16; N_CASTLES]],
+    config: &LocalSearchConfig,
+) -> Polished {
+    let _span = tracing::info_span!("polish", n_restarts = config.n_restarts).entered();
+    let start_score = core::score_against_field(start, field);
+    let (best, best_score) = (0..config.n_restarts.max(1))
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+            let from = match i {
+                0 => start,
+                _ => kick(start, config.max_transfer, &mut rng),
+            };
+            climb(from, field, config, &mut rng)
+        })
+        // The first restart starts from `start`, so this can never do worse than it
+        .max_by_key(|(_, score)| victory_points(score))
+        .expect("There is always at least one restart");
+    Polished {
+        start,
+        start_score,
+        best,
+        best_score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_field(n: usize, seed: u64) -> Vec<[i16; 10]> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| core::generate_uniform_random_distribution_with_rng(&mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn test_polish_never_gets_worse() {
+        let field = random_field(50, 1);
+        let start: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        for strategy in [Strategy::SteepestAscent, Strategy::FirstImprovement] {
+            for tabu_tenure in [0, 5] {
+                let config = LocalSearchConfig {
+                    strategy,
+                    tabu_tenure,
+                    patience: 5,
+                    ..Default::default()
+                };
+                let polished = polish(start, &field, &config);
+                assert!(core::is_valid_distribution(&polished.best));
+                assert!(polished.gain() > 0);
+                assert_eq!(
+                    polished.best_score,
+                    core::score_against_field(polished.best, &field)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_kick_keeps_troops() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let p = core::generate_uniform_random_distribution_with_rng(&mut rng);
+            assert!(core::is_valid_distribution(&kick(p, 5, &mut rng)));
+        }
+    }
+}
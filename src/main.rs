@@ -1,20 +1,45 @@
-#![feature(test)]
+use std::io::IsTerminal;
+use std::path::PathBuf;
 
-mod core;
-mod final_battle;
-mod seventh_battle;
-
-use clap::Parser;
+use clap::{ArgGroup, Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use rayon::prelude::*;
+use rs_battle_for_nation::annealing::{self, AnnealingConfig, Objective, Schedule};
+use rs_battle_for_nation::asymmetric::{self, AsymmetricGame, Player, SideRules};
+use rs_battle_for_nation::checkpoint::{Checkpoint, RunConfig};
+use rs_battle_for_nation::clustering::{self, ClusterConfig, Distance, Method};
+use rs_battle_for_nation::coevolution::{self, CoevolutionConfig};
+use rs_battle_for_nation::fixed_point::{self, Precision};
+use rs_battle_for_nation::free_for_all::{self, FreeForAllConfig};
+use rs_battle_for_nation::local_search::{self, LocalSearchConfig, Strategy};
+use rs_battle_for_nation::metrics::MetricsLayer;
+use rs_battle_for_nation::noise::{self, NoiseModel, NoisyBattle};
+use rs_battle_for_nation::progress::{Progress, Unit};
+use rs_battle_for_nation::repeated::{self, Carryover, MatchConfig};
+use rs_battle_for_nation::report::Side;
+use rs_battle_for_nation::{
+    bootstrap, core, enumerate, equilibrium, field, final_battle, html_report, pruning, report,
+    sensitivity,
+};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 /// The idea for this program is to create a set of half-decent troop distributions by
 /// running many tournaments on uniform randomly troop distributions. The hope is that
 /// the winners of those tournaments will be similar to the troop distributions that
 /// people submit for the contest. Then run a tournament for all of those troop
 /// distributions.
+///
+/// The subcommands give other ways of using the engine.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about=None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// How many to compare at once
     #[arg(short, long, default_value_t = 500)]
     tournament_size: usize,
@@ -22,37 +47,1444 @@ struct Args {
     /// How many tournaments to run
     #[arg(short, long, default_value_t = 10_000)]
     n_tournaments: usize,
+
+    /// How many bootstrap resamples of the winners to run after the final tournament, to
+    /// see how stable its result is. 0 turns bootstrapping off. Each resample is a whole
+    /// final tournament of its own, so with the default field size, this costs about
+    /// this many times as much as the final tournament
+    #[arg(long, default_value_t = 0)]
+    bootstrap: usize,
+
+    /// How many players each bootstrap resample draws from the winners. Defaults to all
+    /// of them, and a smaller field is much cheaper
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    bootstrap_field_size: Option<u64>,
+
+    /// How many of the top finishers of the final tournament to bootstrap
+    #[arg(long, default_value_t = 10)]
+    bootstrap_candidates: usize,
+
+    /// Seed for the random pools. Picked at random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Where to periodically save the progress of the run
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// How many tournaments to finish between checkpoints
+    #[arg(long, default_value_t = 100)]
+    checkpoint_every: usize,
+
+    /// Continue a run from a checkpoint. The tournament size, number of tournaments, and
+    /// seed are all taken from the checkpoint. New checkpoints are saved back to the same
+    /// file, unless `--checkpoint` says otherwise
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// How many of the top finishers of the final tournament to polish with local search
+    /// against the winners of the small tournaments. 0 turns polishing off
+    #[arg(long, default_value_t = 0)]
+    polish: usize,
+
+    /// How many local searches to run for each polished strategy
+    #[arg(long, default_value_t = 4)]
+    polish_restarts: usize,
+
+    /// Largest number of troops moved between two castles in one local search step
+    #[arg(long, default_value_t = 5)]
+    polish_max_transfer: i16,
+
+    /// How many recently visited allocations local search may not go back to. 0 stops
+    /// each search at the first local optimum
+    #[arg(long, default_value_t = 0)]
+    tabu_tenure: usize,
+
+    /// Move to the first better neighbour found, instead of the best one
+    #[arg(long)]
+    first_improvement: bool,
+
+    /// Group the winners of the small tournaments into this many archetypes of strategy,
+    /// after the final tournament. 0 turns clustering off
+    #[arg(long, default_value_t = 0)]
+    archetypes: usize,
+
+    /// Write a self-contained HTML report of the run, with charts, to this file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Allow fractions of a troop, to this many decimal places. Other options that count
+    /// troops, like `--polish-max-transfer`, then count in those fractions, and the
+    /// subcommands read and write allocations in them. A field from `--from-checkpoint`
+    /// is in whatever the checkpoint's run used
+    #[arg(long, global = true, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=fixed_point::MAX_DECIMALS as i64))]
+    decimals: u32,
+
+    /// Write a summary of the time spent in each phase of the run, and how many battles
+    /// were run per second, to this file as JSON
+    #[arg(long)]
+    metrics_json: Option<PathBuf>,
+
+    /// Log every span as it closes to stderr, with how long it took
+    #[arg(long)]
+    trace: bool,
 }
 
-fn create_pool(n_competitors: usize) -> Vec<[i16; 10]> {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Start a local HTTP server with a JSON API for scoring allocations and running
+    /// tournaments. Needs the `server` feature
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Battle two allocations against each other, each given as 10 comma separated
+    /// numbers
+    Battle {
+        /// The first player's allocation
+        p1: String,
+
+        /// The second player's allocation
+        p2: String,
+
+        /// Show who won each castle, and the smallest troop transfer that would change the
+        /// result
+        #[arg(long)]
+        explain: bool,
+    },
+    /// Show how a candidate's wins against a field change under every move of a few troops
+    /// from one castle to another, as a heatmap
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Sensitivity {
+        /// The candidate allocation, as 10 comma separated numbers
+        candidate: String,
+
+        /// Show moves of 1 troop, up to moves of this many troops
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(i16).range(1..))]
+        max_troops: i16,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Search for the allocation that does best against a field with simulated annealing
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Anneal {
+        #[arg(long, value_enum, default_value_t = ObjectiveArg::Wins)]
+        objective: ObjectiveArg,
+
+        #[arg(long, value_enum, default_value_t = ScheduleArg::Geometric)]
+        schedule: ScheduleArg,
+
+        /// How many moves each run tries
+        #[arg(long, default_value_t = 20_000)]
+        steps: usize,
+
+        /// How many runs to do in parallel
+        #[arg(long, default_value_t = 4)]
+        restarts: usize,
+
+        #[arg(long, default_value_t = 5.0)]
+        initial_temperature: f64,
+
+        #[arg(long, default_value_t = 0.05)]
+        final_temperature: f64,
+
+        /// The most troops one move transfers from one castle to another
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(i16).range(1..))]
+        max_transfer: i16,
+
+        /// With the adaptive schedule, the fraction of moves to accept at the start
+        #[arg(long, default_value_t = 0.5)]
+        target_acceptance: f64,
+
+        /// With the reheating schedule, how many steps without a new best before reheating
+        #[arg(long, default_value_t = 2_000)]
+        reheat_after: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Write the best allocation and the score trajectory to this file as JSON
+        #[arg(long)]
+        trajectory_json: Option<PathBuf>,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Evolve a population against a hall of fame of its own past champions, instead of
+    /// against uniform random opponents
+    Coevolve {
+        #[arg(long, default_value_t = 200)]
+        population: usize,
+
+        #[arg(long, default_value_t = 100)]
+        generations: usize,
+
+        /// The most past champions the hall of fame holds
+        #[arg(long, default_value_t = 50)]
+        archive_size: usize,
+
+        /// How far a champion must be from every member of the hall of fame to join it, as
+        /// an L1 distance (twice the troops moved)
+        #[arg(long, default_value_t = 10)]
+        min_archive_distance: i16,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Write the hall of fame to this file, so that it can be used as a `--field`
+        #[arg(long)]
+        save_archive: Option<PathBuf>,
+    },
+    /// Group the strategies in a field into archetypes, and show how well each does
+    /// against the rest of the field
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Archetypes {
+        /// How many archetypes to look for
+        #[arg(short, default_value_t = 6)]
+        k: usize,
+
+        #[arg(long, value_enum, default_value_t = MethodArg::KMedoids)]
+        method: MethodArg,
+
+        #[arg(long, value_enum, default_value_t = DistanceArg::Emd)]
+        distance: DistanceArg,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Write a self-contained HTML report, with charts, of a run saved in a checkpoint.
+    /// The final tournament is run again on the checkpoint's winners
+    Report {
+        /// The checkpoint of the run
+        checkpoint: PathBuf,
+
+        /// Where to write the report
+        #[arg(long, default_value = "report.html")]
+        out: PathBuf,
+    },
+    /// Play every allocation of a small variant of the game against every other one, and
+    /// show each strategy's exact win rate, and the minimax structure of the game
+    Enumerate {
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..=enumerate::MAX_CASTLES))]
+        castles: u64,
+
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(i16).range(0..))]
+        troops: i16,
+
+        /// How many of the best strategies to show
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        /// Write the full summary to this file as JSON
+        #[arg(long)]
+        json: Option<PathBuf>,
+
+        /// Before building the payoff matrix, prune the strategies against a random sample
+        /// of this many of them. The results are then only against the strategies left
+        #[arg(long)]
+        prune: Option<usize>,
+
+        /// Seed for the `--prune` sample
+        #[arg(long, default_value_t = 0, requires = "prune")]
+        prune_seed: u64,
+
+        /// Also solve the game for its optimal mixed strategy with linear programming
+        #[arg(long)]
+        equilibrium: bool,
+    },
+    /// Solve the game restricted to a set of allocations, like a pruned pool, for its
+    /// optimal mixed strategy with linear programming
+    Equilibrium {
+        /// A file with one comma separated allocation per line
+        strategies: PathBuf,
+
+        /// Write the equilibrium to this file as JSON
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Shrink a set of candidate allocations by collapsing the ones that get the same
+    /// result against every member of a field, and dropping the ones that are weakly
+    /// dominated against it
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Prune {
+        /// A file with one comma separated candidate allocation per line
+        candidates: PathBuf,
+
+        /// Write the candidates that are left to this file
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Play two populations against each other, in a game where the sides can have
+    /// different troop budgets, and value the castles differently. Each side's results
+    /// are counted by its own values
+    Asymmetric {
+        #[arg(long, default_value_t = core::N_TROOPS, value_parser = clap::value_parser!(i16).range(1..))]
+        a_budget: i16,
+
+        #[arg(long, default_value_t = core::N_TROOPS, value_parser = clap::value_parser!(i16).range(1..))]
+        b_budget: i16,
+
+        /// What the castles are worth to side A, as 10 comma separated numbers. The
+        /// default is castle `i` being worth `i`
+        #[arg(long, value_parser = asymmetric::parse_values)]
+        a_values: Option<[f32; 10]>,
+
+        /// What the castles are worth to side B
+        #[arg(long, value_parser = asymmetric::parse_values)]
+        b_values: Option<[f32; 10]>,
+
+        /// How many uniform random allocations each side's pool has
+        #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u64).range(1..))]
+        pool: u64,
+
+        /// How many of the best of each side to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Search for each side's best response to the other side's pool, with this many
+        /// hill climbs. 0 turns it off
+        #[arg(long, default_value_t = 0)]
+        best_response: usize,
+
+        /// Solve for each side's security strategy, the mix that does best in the worst
+        /// case, over this many of the best of each side. 0 turns it off. Unless the sides
+        /// value the castles the same, these are not an equilibrium
+        #[arg(long, default_value_t = 0)]
+        security: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Play a field in free-for-all matches of more than two players at once, where each
+    /// castle goes to whoever sends the most troops, and the players are reshuffled into
+    /// new matches every round
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    FreeForAll {
+        /// How many players are in each match
+        #[arg(short = 'k', long, default_value_t = 4, value_parser = clap::value_parser!(u64).range(2..))]
+        match_size: u64,
+
+        #[arg(long, default_value_t = 20)]
+        rounds: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// How many of the best players to show
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Play two policies against each other over several rounds, each given an opening
+    /// allocation, and show every round
+    Repeated {
+        /// The first player's opening allocation
+        p1: String,
+
+        /// The second player's opening allocation
+        p2: String,
+
+        #[arg(long, value_enum, default_value_t = PolicyArg::Static)]
+        p1_policy: PolicyArg,
+
+        #[arg(long, value_enum, default_value_t = PolicyArg::Static)]
+        p2_policy: PolicyArg,
+
+        #[arg(long, default_value_t = 10)]
+        rounds: usize,
+
+        /// Troops sent to a lost castle are destroyed, and the survivors are the next
+        /// round's budget
+        #[arg(long)]
+        attrition: bool,
+
+        /// With attrition, how many troops each player gets on top of its survivors
+        #[arg(long, default_value_t = 0, requires = "attrition", value_parser = clap::value_parser!(i16).range(0..))]
+        reinforcements: i16,
+    },
+    /// Score a field against itself when the troops that arrive are not the ones that
+    /// were sent, and rank the allocations that hold up best under the noise
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Noisy {
+        #[arg(long, value_enum, default_value_t = NoiseArg::Binomial)]
+        noise: NoiseArg,
+
+        /// With binomial noise, the chance that each troop arrives. With --decimals, each
+        /// fraction of a troop arrives or not on its own
+        #[arg(long, default_value_t = 0.9)]
+        survival: f64,
+
+        /// With jitter, the most troops each castle can be off by
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(i16).range(0..))]
+        jitter: i16,
+
+        /// With misrouting, the chance that each troop goes to a random castle instead
+        #[arg(long, default_value_t = 0.05)]
+        misroute: f64,
+
+        /// How many battles to simulate for each pair, when the noise cannot be worked
+        /// out exactly
+        #[arg(long, default_value_t = 1_000)]
+        samples: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Only score this allocation against the field
+        #[arg(long)]
+        candidate: Option<String>,
+
+        /// How many of the best allocations to show
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+    /// Explore a field in an interactive terminal UI: a sortable leaderboard, live scoring
+    /// of a typed in allocation, and per-castle histograms. Needs the `tui` feature
+    #[command(group(ArgGroup::new("field_source").required(true).multiple(false)))]
+    Tui {
+        #[command(flatten)]
+        field: FieldArgs,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ObjectiveArg {
+    /// Maximise the number of wins
+    Wins,
+    /// Maximise wins plus half the ties
+    Vp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ScheduleArg {
+    /// Cool by the same factor every step
+    Geometric,
+    /// Adjust the temperature to track a falling acceptance rate
+    Adaptive,
+    /// Cool geometrically, but reheat when stuck
+    Reheating,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PolicyArg {
+    /// Play the opening allocation every round
+    Static,
+    /// Play the cheapest allocation that wins the most points against the opponent's
+    /// last one
+    Counter,
+    /// Play the opponent's last allocation
+    Copy,
+}
+
+impl PolicyArg {
+    fn policy(self, opening: [i16; 10]) -> Box<dyn core::Policy> {
+        match self {
+            PolicyArg::Static => Box::new(opening),
+            PolicyArg::Counter => Box::new(repeated::CounterLast { opening }),
+            PolicyArg::Copy => Box::new(repeated::CopyLast { opening }),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum NoiseArg {
+    /// Each troop is lost with some chance
+    Binomial,
+    /// Each castle gets a few troops more or fewer than were sent
+    Jitter,
+    /// Each troop goes to a random castle with some chance
+    Misroute,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum MethodArg {
+    KMeans,
+    KMedoids,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum DistanceArg {
+    /// Troops that differ, castle by castle
+    L1,
+    /// Earth mover's distance, troops moved times castles moved along
+    Emd,
+}
+
+/// Where a subcommand gets its field of opponents from. Exactly one of `--field`,
+/// `--from-checkpoint` and `--random` must be given.
+#[derive(ClapArgs, Debug)]
+#[group(skip)]
+struct FieldArgs {
+    /// Read the field from a file with one comma separated allocation per line
+    #[arg(long, group = "field_source")]
+    field: Option<PathBuf>,
+
+    /// Use the tournament winners saved in a checkpoint as the field
+    #[arg(long, group = "field_source")]
+    from_checkpoint: Option<PathBuf>,
+
+    /// Use this many uniform random allocations as the field
+    #[arg(long, group = "field_source")]
+    random: Option<usize>,
+
+    /// Seed for the `--random` field
+    #[arg(long, default_value_t = 0, requires = "random")]
+    field_seed: u64,
+}
+
+impl FieldArgs {
+    /// load reads the field in units of `precision`, the one given by `--decimals`.
+    /// A checkpoint's field is in the units of the run that saved it, so `--decimals`
+    /// must either agree with it or not be given. Returns the field, and the precision
+    /// it is in.
+    fn load(&self, precision: Precision) -> (Vec<[i16; 10]>, Precision) {
+        if let Some(path) = &self.field {
+            let field = field::read_field_with_precision(path, precision)
+                .expect("Could not read the field");
+            (field, precision)
+        } else if let Some(path) = &self.from_checkpoint {
+            let checkpoint = Checkpoint::load(path).expect("Could not load the checkpoint");
+            let saved = checkpoint.config.precision();
+            if precision != Precision::WHOLE && precision != saved {
+                eprintln!(
+                    "{} is counted in {saved}, but --decimals asks for {precision}",
+                    path.display()
+                );
+                std::process::exit(1);
+            }
+            (checkpoint.winners, saved)
+        } else {
+            let n = self.random.expect("clap requires one of the field sources");
+            let field = create_pool(n, precision, &mut StdRng::seed_from_u64(self.field_seed));
+            (field, precision)
+        }
+    }
+}
+
+/// parse_candidate reads an allocation given on the command line in troops, to the
+/// decimal places of `precision`, and exits with an error if it is not valid
+fn parse_candidate(s: &str, precision: Precision) -> [i16; 10] {
+    precision.parse_distribution(s).unwrap_or_else(|e| {
+        eprintln!("Invalid allocation {s:?}: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// require_whole_troops exits with an error when a subcommand that only works in whole
+/// troops is asked for fractions of one
+fn require_whole_troops(precision: Precision, subcommand: &str) {
+    if precision != Precision::WHOLE {
+        eprintln!("{subcommand} only works in whole troops, not {precision}");
+        std::process::exit(1);
+    }
+}
+
+/// troops_to_units converts a whole number of troops given to `flag` into units of
+/// `precision`, and exits with an error if that is too many units to count
+fn troops_to_units(troops: i16, precision: Precision, flag: &str) -> i16 {
+    troops.checked_mul(precision.scale()).unwrap_or_else(|| {
+        eprintln!("{flag} {troops} is too many troops to count in {precision}");
+        std::process::exit(1);
+    })
+}
+
+fn run_command(command: &Command, precision: Precision) {
+    match command {
+        Command::Serve { addr } => {
+            require_whole_troops(precision, "serve");
+            serve(addr)
+        }
+        Command::Battle { p1, p2, explain } => {
+            let p1 = parse_candidate(p1, precision);
+            let p2 = parse_candidate(p2, precision);
+            if *explain {
+                println!("{}", report::explain(p1, p2).format(precision));
+            } else {
+                let (p1_score, p2_score) = core::battle(p1, p2);
+                println!("{p1_score} to {p2_score}");
+            }
+        }
+        Command::Sensitivity {
+            candidate,
+            max_troops,
+            field,
+        } => {
+            let (field, precision) = field.load(precision);
+            let candidate = parse_candidate(candidate, precision);
+            let color = std::io::stdout().is_terminal();
+            for troops in 1..=*max_troops {
+                let s = sensitivity::sensitivity(candidate, &field, troops);
+                println!("{}", s.heatmap(color, precision));
+            }
+        }
+        Command::Anneal {
+            objective,
+            schedule,
+            steps,
+            restarts,
+            initial_temperature,
+            final_temperature,
+            max_transfer,
+            target_acceptance,
+            reheat_after,
+            seed,
+            trajectory_json,
+            field,
+        } => {
+            let (field, precision) = field.load(precision);
+            let max_transfer = troops_to_units(*max_transfer, precision, "--max-transfer");
+            let config = AnnealingConfig {
+                objective: match objective {
+                    ObjectiveArg::Wins => Objective::Wins,
+                    ObjectiveArg::Vp => Objective::VictoryPoints,
+                },
+                schedule: match schedule {
+                    ScheduleArg::Geometric => Schedule::Geometric,
+                    ScheduleArg::Adaptive => Schedule::Adaptive {
+                        target_acceptance: *target_acceptance,
+                    },
+                    ScheduleArg::Reheating => Schedule::Reheating {
+                        patience: *reheat_after,
+                    },
+                },
+                initial_temperature: *initial_temperature,
+                final_temperature: *final_temperature,
+                n_steps: *steps,
+                max_transfer,
+                n_restarts: *restarts,
+                precision,
+                seed: *seed,
+            };
+            if let Err(e) = config.validate() {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            let res = annealing::anneal(&field, &config);
+            println!(
+                "{:>8} {:>12} {:>10} {:>10}",
+                "step", "temperature", "current", "best"
+            );
+            for point in res.trajectory.iter().step_by(10) {
+                println!(
+                    "{:>8} {:>12.4} {:>10.1} {:>10.1}",
+                    point.step, point.temperature, point.current, point.best
+                );
+            }
+            println!(
+                "Best allocation is {}, with {} wins, {} ties and {} losses against {} players",
+                precision.format_distribution(&res.best),
+                res.best_score.wins,
+                res.best_score.ties,
+                res.best_score.losses,
+                field.len()
+            );
+            if let Some(path) = trajectory_json {
+                let json =
+                    serde_json::to_string_pretty(&res).expect("Could not serialize the result");
+                std::fs::write(path, json).expect("Could not write the trajectory file");
+            }
+        }
+        Command::Coevolve {
+            population,
+            generations,
+            archive_size,
+            min_archive_distance,
+            seed,
+            save_archive,
+        } => {
+            require_whole_troops(precision, "coevolve");
+            let config = CoevolutionConfig {
+                population_size: *population,
+                n_generations: *generations,
+                archive_size: *archive_size,
+                min_archive_distance: *min_archive_distance,
+                seed: *seed,
+                ..Default::default()
+            };
+            let res = coevolution::coevolve(&config);
+            let print_every = (res.history.len() / 20).max(1);
+            for stats in res.history.iter().step_by(print_every) {
+                println!(
+                    "Generation {:>5}: champion {:?} ({} wins), hall of fame {}",
+                    stats.generation, stats.champion, stats.champion_score.wins, stats.archive_size
+                );
+            }
+            println!("Final champion is {:?}", res.champion);
+            if let Some(path) = save_archive {
+                field::write_field(path, &res.archive).expect("Could not write the hall of fame");
+            }
+        }
+        Command::Archetypes {
+            k,
+            method,
+            distance,
+            seed,
+            field,
+        } => {
+            let config = ClusterConfig {
+                k: *k,
+                method: match method {
+                    MethodArg::KMeans => Method::KMeans,
+                    MethodArg::KMedoids => Method::KMedoids,
+                },
+                distance: match distance {
+                    DistanceArg::L1 => Distance::L1,
+                    DistanceArg::Emd => Distance::EarthMovers,
+                },
+                seed: *seed,
+                ..Default::default()
+            };
+            let (field, precision) = field.load(precision);
+            print_archetypes(&field, &config, precision);
+        }
+        Command::Report { checkpoint, out } => {
+            let run = Checkpoint::load(checkpoint).expect("Could not load the checkpoint");
+            let progress =
+                Progress::new(Unit::Rounds, run.winners.len().saturating_sub(2) as u64, 0);
+            let res = final_battle::tournament_with_progress(&run.winners, &progress);
+            progress.finish();
+            write_report(out, &run.config, &run.winners, &res, None);
+        }
+        Command::Enumerate {
+            castles,
+            troops,
+            top,
+            json,
+            prune,
+            prune_seed,
+            equilibrium,
+        } => {
+            require_whole_troops(precision, "enumerate");
+            let variant = enumerate::Variant {
+                n_castles: *castles as usize,
+                n_troops: *troops,
+            };
+            let n_strategies = variant.n_strategies();
+            let limit = if prune.is_some() {
+                MAX_PRUNED_STRATEGIES
+            } else {
+                enumerate::MAX_STRATEGIES
+            };
+            if n_strategies > limit {
+                eprintln!(
+                    "{castles} castles and {troops} troops have {n_strategies} strategies, more than the {limit} that can be enumerated"
+                );
+                std::process::exit(1);
+            }
+            println!(
+                "Enumerating {n_strategies} strategies of {castles} castles and {troops} troops"
+            );
+            let mut strategies = enumerate::all_allocations(variant);
+            if let Some(n_sample) = prune {
+                let mut rng = StdRng::seed_from_u64(*prune_seed);
+                let sample: Vec<Vec<i16>> = strategies
+                    .choose_multiple(&mut rng, *n_sample)
+                    .cloned()
+                    .collect();
+                let pruned = pruning::prune(&strategies, &sample);
+                print_pruning(&pruned);
+                if pruned.kept.len() as u64 > enumerate::MAX_STRATEGIES {
+                    eprintln!(
+                        "{} strategies are left after pruning, more than the {} that can be enumerated. A smaller sample prunes more",
+                        pruned.kept.len(),
+                        enumerate::MAX_STRATEGIES
+                    );
+                    std::process::exit(1);
+                }
+                strategies = pruned.kept;
+            }
+            if *equilibrium && strategies.len() > equilibrium::MAX_LP_STRATEGIES {
+                eprintln!(
+                    "{} strategies are too many to solve for an equilibrium, the most is {}. Try --prune",
+                    strategies.len(),
+                    equilibrium::MAX_LP_STRATEGIES
+                );
+                std::process::exit(1);
+            }
+            let matrix = enumerate::PayoffMatrix::build(&strategies);
+            let summary = enumerate::summarize(variant, &strategies, &matrix);
+            println!(
+                "{:<32} {:>8} {:>8} {:>8} {:>9}",
+                "strategy", "wins", "ties", "losses", "win rate"
+            );
+            for s in summary.strategies.iter().take(*top) {
+                println!(
+                    "{:<32} {:>8} {:>8} {:>8} {:>9.4}",
+                    format!("{:?}", s.allocation),
+                    s.wins,
+                    s.ties,
+                    s.losses,
+                    s.win_rate
+                );
+            }
+            println!(
+                "Pure strategy maximin is {}, and minimax is {}",
+                summary.maximin, summary.minimax
+            );
+            match summary.unbeaten.len() {
+                0 => println!("Every strategy can be beaten, so there is no pure saddle point"),
+                n => println!("{n} strategies are never beaten: {:?}", summary.unbeaten),
+            }
+            if *equilibrium {
+                print_equilibrium(
+                    &strategies,
+                    &equilibrium::solve_payoff_matrix(&matrix),
+                    Precision::WHOLE,
+                    "optimal mixed strategy",
+                );
+            }
+            if let Some(path) = json {
+                let json = serde_json::to_string_pretty(&summary)
+                    .expect("Could not serialize the summary");
+                std::fs::write(path, json).expect("Could not write the summary");
+            }
+        }
+        Command::Prune {
+            candidates,
+            out,
+            field,
+        } => {
+            let (field, precision) = field.load(precision);
+            let candidates = field::read_field_with_precision(candidates, precision)
+                .expect("Could not read the candidates");
+            let pruned = pruning::prune(&candidates, &field);
+            print_pruning(&pruned);
+            if let Some(path) = out {
+                field::write_field_with_precision(path, &pruned.kept, precision)
+                    .expect("Could not write the candidates");
+            }
+        }
+        Command::Equilibrium { strategies, json } => {
+            let strategies: Vec<Vec<i16>> = field::read_field_with_precision(strategies, precision)
+                .expect("Could not read the strategies")
+                .iter()
+                .map(|p| p.to_vec())
+                .collect();
+            if strategies.len() > equilibrium::MAX_LP_STRATEGIES {
+                eprintln!(
+                    "{} strategies are too many to solve for an equilibrium, the most is {}. Try pruning them first",
+                    strategies.len(),
+                    equilibrium::MAX_LP_STRATEGIES
+                );
+                std::process::exit(1);
+            }
+            let matrix = enumerate::PayoffMatrix::build(&strategies);
+            let eq = equilibrium::solve_payoff_matrix(&matrix);
+            print_equilibrium(&strategies, &eq, precision, "optimal mixed strategy");
+            if let Some(path) = json {
+                let json =
+                    serde_json::to_string_pretty(&eq).expect("Could not serialize the equilibrium");
+                std::fs::write(path, json).expect("Could not write the equilibrium");
+            }
+        }
+        Command::Asymmetric {
+            a_budget,
+            b_budget,
+            a_values,
+            b_values,
+            pool,
+            top,
+            best_response,
+            security,
+            seed,
+        } => {
+            require_whole_troops(precision, "asymmetric");
+            let default_values = SideRules::default().values;
+            let game = AsymmetricGame {
+                a: SideRules {
+                    budget: *a_budget,
+                    values: a_values.unwrap_or(default_values),
+                },
+                b: SideRules {
+                    budget: *b_budget,
+                    values: b_values.unwrap_or(default_values),
+                },
+            };
+            if *security > equilibrium::MAX_LP_STRATEGIES {
+                eprintln!(
+                    "The most strategies that can be solved for a security strategy is {}",
+                    equilibrium::MAX_LP_STRATEGIES
+                );
+                std::process::exit(1);
+            }
+            let mut rng = StdRng::seed_from_u64(*seed);
+            let a_pool: Vec<[i16; 10]> = (0..*pool)
+                .map(|_| game.a.generate_with_rng(&mut rng))
+                .collect();
+            let b_pool: Vec<[i16; 10]> = (0..*pool)
+                .map(|_| game.b.generate_with_rng(&mut rng))
+                .collect();
+            let ranking = game.tournament(&a_pool, &b_pool);
+            for (player, ranked, other_pool) in [
+                (Player::A, &ranking.a, &b_pool),
+                (Player::B, &ranking.b, &a_pool),
+            ] {
+                let rules = game.rules(player);
+                println!(
+                    "Side {player:?}: {} troops, castle values {:?}",
+                    rules.budget, rules.values
+                );
+                println!(
+                    "{:<44} {:>8} {:>8} {:>8}",
+                    "allocation", "wins", "ties", "losses"
+                );
+                for (p, score) in ranked.iter().take(*top) {
+                    println!(
+                        "{:<44} {:>8} {:>8} {:>8}",
+                        format!("{p:?}"),
+                        score.wins,
+                        score.ties,
+                        score.losses
+                    );
+                }
+                if *best_response > 0 {
+                    let config = LocalSearchConfig {
+                        n_restarts: *best_response,
+                        seed: *seed,
+                        ..Default::default()
+                    };
+                    let (best, score) = game.best_response(player, other_pool, &config);
+                    println!(
+                        "Best response to the other side's pool is {best:?}, with {} wins, {} ties and {} losses",
+                        score.wins, score.ties, score.losses
+                    );
+                }
+            }
+            if *security > 0 {
+                let a_top: Vec<[i16; 10]> =
+                    ranking.a.iter().take(*security).map(|(p, _)| *p).collect();
+                let b_top: Vec<[i16; 10]> =
+                    ranking.b.iter().take(*security).map(|(p, _)| *p).collect();
+                for (player, own, other) in
+                    [(Player::A, &a_top, &b_top), (Player::B, &b_top, &a_top)]
+                {
+                    let eq = game.security_strategy(player, own, other);
+                    let strategies: Vec<Vec<i16>> = own.iter().map(|p| p.to_vec()).collect();
+                    println!(
+                        "Side {player:?}'s security strategy over its top {}:",
+                        own.len()
+                    );
+                    print_equilibrium(&strategies, &eq, Precision::WHOLE, "security strategy");
+                }
+            }
+        }
+        Command::FreeForAll {
+            match_size,
+            rounds,
+            seed,
+            top,
+            field,
+        } => {
+            let config = FreeForAllConfig {
+                match_size: *match_size as usize,
+                n_rounds: *rounds,
+                seed: *seed,
+            };
+            let (field, precision) = field.load(precision);
+            let standings = free_for_all::tournament(&field, &config);
+            println!(
+                "{:<44} {:>10} {:>12} {:>8}",
+                "player", "match wins", "mean points", "matches"
+            );
+            for s in standings.iter().rev().take(*top) {
+                println!(
+                    "{:<44} {:>10.2} {:>12.2} {:>8}",
+                    precision.format_distribution(&s.player),
+                    s.match_wins,
+                    s.points / s.n_matches.max(1) as f32,
+                    s.n_matches
+                );
+            }
+        }
+        Command::Repeated {
+            p1,
+            p2,
+            p1_policy,
+            p2_policy,
+            rounds,
+            attrition,
+            reinforcements,
+        } => {
+            let config = MatchConfig {
+                n_rounds: *rounds,
+                carryover: if *attrition {
+                    Carryover::Attrition {
+                        reinforcements: troops_to_units(
+                            *reinforcements,
+                            precision,
+                            "--reinforcements",
+                        ),
+                    }
+                } else {
+                    Carryover::Reset
+                },
+                budget: precision.budget(),
+            };
+            let res = repeated::play_match(
+                p1_policy.policy(parse_candidate(p1, precision)).as_mut(),
+                p2_policy.policy(parse_candidate(p2, precision)).as_mut(),
+                &config,
+            );
+            println!(
+                "{:>5}  {:<44} {:<44} {:>6} {:>6}  winner",
+                "round", "player 1", "player 2", "p1", "p2"
+            );
+            for (idx, r) in res.rounds.iter().enumerate() {
+                println!(
+                    "{:>5}  {:<44} {:<44} {:>6} {:>6}  {}",
+                    idx + 1,
+                    precision.format_distribution(&r.p1),
+                    precision.format_distribution(&r.p2),
+                    r.p1_score,
+                    r.p2_score,
+                    r.winner
+                );
+            }
+            println!(
+                "Player 1 won {} rounds, player 2 won {}, and {} were tied",
+                res.p1_round_wins, res.p2_round_wins, res.ties
+            );
+            match res.winner() {
+                Side::Tie => println!("The match is a tie"),
+                winner => println!("{winner} wins the match"),
+            }
+        }
+        Command::Noisy {
+            noise,
+            survival,
+            jitter,
+            misroute,
+            samples,
+            seed,
+            candidate,
+            top,
+            field,
+        } => {
+            if !(0.0..=1.0).contains(survival) || !(0.0..=1.0).contains(misroute) {
+                eprintln!("--survival and --misroute must be between 0 and 1");
+                std::process::exit(1);
+            }
+            let (field, precision) = field.load(precision);
+            let model = match noise {
+                NoiseArg::Binomial => NoiseModel::Binomial {
+                    survival: *survival,
+                },
+                NoiseArg::Jitter => NoiseModel::Jitter {
+                    k: troops_to_units(*jitter, precision, "--jitter"),
+                },
+                NoiseArg::Misroute => NoiseModel::Misroute {
+                    probability: *misroute,
+                },
+            };
+            let candidates = match candidate {
+                Some(c) => vec![parse_candidate(c, precision)],
+                None => field.clone(),
+            };
+            let mut allocations = candidates.clone();
+            allocations.extend_from_slice(&field);
+            let battles = NoisyBattle::for_allocations(model, *samples, *seed, &allocations);
+            let draws =
+                battles.monte_carlo_draws(candidates.len(), field.len(), precision.budget());
+            if draws > noise::MAX_MONTE_CARLO_DRAWS {
+                eprintln!(
+                    "Estimating {model:?} would take about {draws} random draws, more than the {} allowed. Give a --candidate, or use fewer --samples or a smaller field",
+                    noise::MAX_MONTE_CARLO_DRAWS
+                );
+                std::process::exit(1);
+            }
+            if model.is_exact() {
+                println!("Scoring {model:?} exactly");
+            } else {
+                println!("Estimating {model:?} from {samples} battles per pair");
+            }
+            // Both scores are wins plus half the ties
+            let mut scored: Vec<([i16; 10], f64, f64)> = candidates
+                .iter()
+                .map(|&c| {
+                    let noisy = battles.score_against_field(&c, &field);
+                    let noiseless = core::score_against_field(c, &field);
+                    let noiseless = noiseless.wins as f64 + noiseless.ties as f64 / 2.0;
+                    (c, noisy.expected_victory_points(), noiseless)
+                })
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            println!(
+                "{:<44} {:>12} {:>15}",
+                "allocation", "noisy score", "noiseless score"
+            );
+            for (c, noisy, noiseless) in scored.iter().take(*top) {
+                println!(
+                    "{:<44} {:>12.2} {:>15.1}",
+                    precision.format_distribution(c),
+                    noisy,
+                    noiseless
+                );
+            }
+        }
+        Command::Tui { field } => {
+            let (field, precision) = field.load(precision);
+            require_whole_troops(precision, "tui");
+            tui(field)
+        }
+    }
+}
+
+/// Prints what a mixed strategy from `equilibrium::solve` guarantees, and the strategies
+/// it plays, most likely first. `mix` names it, like "optimal mixed strategy" for a
+/// zero-sum game, where it guarantees the game's value.
+fn print_equilibrium(
+    strategies: &[Vec<i16>],
+    eq: &equilibrium::Equilibrium,
+    precision: Precision,
+    mix: &str,
+) {
+    let mut support = eq.row_support();
+    support.sort_by(|&a, &b| eq.row_strategy[b].total_cmp(&eq.row_strategy[a]));
+    println!(
+        "The {mix} guarantees {:.4}, and plays {} of the {} strategies",
+        eq.value,
+        support.len(),
+        strategies.len()
+    );
+    println!("{:<44} {:>11}", "strategy", "probability");
+    for idx in support {
+        println!(
+            "{:<44} {:>11.4}",
+            format!(
+                "[{}]",
+                strategies[idx]
+                    .iter()
+                    .map(|&t| precision.format(t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            eq.row_strategy[idx]
+        );
+    }
+}
+
+/// The most strategies `enumerate --prune` will prune. Pruning is much cheaper than the
+/// full payoff matrix, since each one is only played against the sample.
+const MAX_PRUNED_STRATEGIES: u64 = 2_000_000;
+
+fn print_pruning<S>(pruned: &pruning::Pruned<S>) {
+    println!(
+        "Pruned {} candidates to {} distinct ones, and {} that are not weakly dominated, {:.1}% fewer",
+        pruned.n_candidates,
+        pruned.n_distinct,
+        pruned.n_undominated,
+        100.0 * pruned.reduction()
+    );
+}
+
+#[cfg(feature = "server")]
+fn serve(addr: &str) {
+    rs_battle_for_nation::server::serve(addr).expect("The server stopped with an error");
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_addr: &str) {
+    eprintln!("This binary was built without the server, rebuild it with `--features server`");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "tui")]
+fn tui(field: Vec<[i16; 10]>) {
+    rs_battle_for_nation::tui::run(field).expect("The terminal UI stopped with an error");
+}
+
+#[cfg(not(feature = "tui"))]
+fn tui(_field: Vec<[i16; 10]>) {
+    eprintln!("This binary was built without the terminal UI, rebuild it with `--features tui`");
+    std::process::exit(1);
+}
+
+/// print_archetypes plays `players` against each other, groups them into archetypes, and
+/// prints a table of them
+fn print_archetypes(players: &[[i16; 10]], config: &ClusterConfig, precision: Precision) {
+    let scored = core::run_battles_slice(players, None);
+    let archetypes = clustering::archetypes(&scored, config);
+    println!(
+        "{:<36} {:>6} {:>10} {:>10}  {:<44} centroid",
+        "archetype", "size", "mean wins", "mean VP", "representative"
+    );
+    for a in archetypes {
+        let centroid: Vec<String> = a
+            .centroid
+            .iter()
+            .map(|t| format!("{:.1}", t / f64::from(precision.scale())))
+            .collect();
+        println!(
+            "{:<36} {:>6} {:>10.1} {:>10.1}  {:<44} [{}]",
+            a.label,
+            a.size,
+            a.mean_wins,
+            a.mean_victory_points,
+            precision.format_distribution(&a.representative),
+            centroid.join(", ")
+        );
+    }
+}
+
+/// How many players of the field to put in a report. Whole pools are regenerated from the
+/// seed until there are at least this many.
+const REPORT_FIELD_SIZE: usize = 5_000;
+
+/// write_report writes the HTML report. `res` is the result of the final tournament, from
+/// last place to first.
+fn write_report(
+    path: &std::path::Path,
+    config: &RunConfig,
+    winners: &[[i16; 10]],
+    res: &[[i16; 10]],
+    metrics: Option<&rs_battle_for_nation::metrics::MetricsSummary>,
+) {
+    let n_pools = REPORT_FIELD_SIZE
+        .div_ceil(config.tournament_size.max(1))
+        .clamp(1, config.n_tournaments.max(1));
+    let field: Vec<[i16; 10]> = (0..n_pools)
+        .flat_map(|i| {
+            let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+            create_pool(config.tournament_size, config.precision(), &mut rng)
+        })
+        .collect();
+    let ranking: Vec<[i16; 10]> = res.iter().rev().copied().collect();
+    let html = html_report::render(&html_report::ReportData {
+        config: *config,
+        field: &field,
+        winners,
+        ranking: &ranking,
+        metrics,
+    });
+    std::fs::write(path, html).expect("Could not write the report");
+    println!("Wrote the report to {}", path.display());
+}
+
+fn create_pool<R: rand::Rng>(
+    n_competitors: usize,
+    precision: Precision,
+    rng: &mut R,
+) -> Vec<[i16; 10]> {
+    let _span = tracing::debug_span!("pool_generation", n_competitors).entered();
     (0..n_competitors)
-        .map(|_| core::generate_uniform_random_distribution())
+        .map(|_| precision.generate_uniform_random_distribution(rng))
         .collect()
 }
 
 fn main() {
     let start_time = std::time::Instant::now();
     let args = Args::parse();
-    println!("Setting up tournaments");
-
-    // Set up and run `n_tournaments`
-    let winners: Vec<[i16; 10]> = (0..args.n_tournaments)
-        // Start up tournaments in parallel
-        .into_par_iter()
-        // Create the uniform random pools
-        .map(|_| create_pool(args.tournament_size))
-        // Run all the tournaments
-        .map(|players| final_battle::tournament(&players, false))
-        // Get the best performer of each
-        .map(|res| *res.last().expect("The tournament produced an empty vector"))
-        .collect();
+
+    let metrics_layer = MetricsLayer::new();
+    let trace_layer = args.trace.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_span_events(FmtSpan::CLOSE)
+    });
+    tracing_subscriber::registry()
+        .with(metrics_layer.clone())
+        .with(trace_layer)
+        .init();
+
+    if let Some(command) = &args.command {
+        run_command(command, Precision::new(args.decimals));
+        return;
+    }
+
+    let mut run = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path).expect("Could not load the checkpoint");
+            println!(
+                "Resuming from {} with {} of {} tournaments finished",
+                path.display(),
+                checkpoint.next_tournament(),
+                checkpoint.config.n_tournaments
+            );
+            checkpoint
+        }
+        None => Checkpoint::new(RunConfig {
+            tournament_size: args.tournament_size,
+            n_tournaments: args.n_tournaments,
+            seed: args.seed.unwrap_or_else(rand::random),
+            decimals: args.decimals,
+        }),
+    };
+    let config = run.config;
+    let precision = config.precision();
+    let checkpoint_path = args.checkpoint.clone().or_else(|| args.resume.clone());
+    // Without anywhere to save to, there is no need to stop and checkpoint
+    let batch_size = match checkpoint_path {
+        Some(_) => args.checkpoint_every.max(1),
+        None => config.n_tournaments.max(1),
+    };
+    println!("Setting up tournaments with seed {}", config.seed);
+
+    let tournament_progress = Progress::new(
+        Unit::Tournaments,
+        config.n_tournaments as u64,
+        run.next_tournament() as u64,
+    );
+
+    // Set up and run `n_tournaments`, a batch at a time
+    while !run.is_finished() {
+        let start = run.next_tournament();
+        let end = (start + batch_size).min(config.n_tournaments);
+        let batch_winners: Vec<[i16; 10]> = (start..end)
+            // Start up tournaments in parallel
+            .into_par_iter()
+            // Create the uniform random pools
+            .map(|i| {
+                let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(i as u64));
+                create_pool(config.tournament_size, precision, &mut rng)
+            })
+            // Run all the tournaments
+            .map(|players| {
+                let _span = tracing::debug_span!("tournament").entered();
+                let res = final_battle::tournament_with_progress(&players, &tournament_progress);
+                tournament_progress.tournament_finished();
+                res
+            })
+            // Get the best performer of each
+            .map(|res| *res.last().expect("The tournament produced an empty vector"))
+            .collect();
+        run.winners.extend(batch_winners);
+
+        if let Some(path) = &checkpoint_path {
+            run.save(path).expect("Could not save the checkpoint");
+        }
+    }
+    tournament_progress.finish();
+    let winners = run.winners;
 
     println!("Running final tournament of winners of small tournaments");
 
     // Finally, run a tournament with all the winners
-    let res = final_battle::tournament(&winners, true);
+    let final_span = tracing::info_span!("final_tournament", n_players = winners.len()).entered();
+    let final_progress = Progress::new(Unit::Rounds, winners.len().saturating_sub(2) as u64, 0);
+    let res = final_battle::tournament_with_progress(&winners, &final_progress);
+    final_progress.finish();
+    final_span.exit();
     let winner: &[i16; 10] = res.last().expect("The tournament produced an empty vector");
 
-    println!("Final winner is {:?}", winner);
-    println!("Run time was {}s", start_time.elapsed().as_secs());
+    println!("Final winner is {}", precision.format_distribution(winner));
+
+    if args.bootstrap > 0 {
+        let field_size = args
+            .bootstrap_field_size
+            .map_or(winners.len(), |n| n as usize);
+        println!(
+            "Bootstrapping the final tournament {} times, against {field_size} resampled players",
+            args.bootstrap
+        );
+        let candidates: Vec<[i16; 10]> = res
+            .iter()
+            .rev()
+            .take(args.bootstrap_candidates)
+            .copied()
+            .collect();
+        if candidates.len() + field_size < 2 {
+            eprintln!("Bootstrapping needs at least 2 players between --bootstrap-candidates and --bootstrap-field-size");
+            std::process::exit(1);
+        }
+        let stats = bootstrap::bootstrap(
+            &candidates,
+            &winners,
+            field_size,
+            args.bootstrap,
+            config.seed,
+        );
+        println!(
+            "{:<44} {:>21} {:>21} {:>21}",
+            "candidate", "P(win)", "P(top 10)", "percentile"
+        );
+        if stats.is_empty() {
+            println!(
+                "Every resample drew fewer than 2 different players, so there is nothing to report"
+            );
+        }
+        for s in stats {
+            println!(
+                "{:<44} {:>6.3} [{:.3}, {:.3}] {:>6.3} [{:.3}, {:.3}] {:>6.2} [{:.2}, {:.2}]",
+                precision.format_distribution(&s.candidate),
+                s.p_win.mean,
+                s.p_win.lower,
+                s.p_win.upper,
+                s.p_top_10.mean,
+                s.p_top_10.lower,
+                s.p_top_10.upper,
+                s.percentile.mean,
+                s.percentile.lower,
+                s.percentile.upper,
+            );
+        }
+    }
+    if args.polish > 0 {
+        println!(
+            "Polishing the top {} finishers with local search",
+            args.polish
+        );
+        let config = LocalSearchConfig {
+            strategy: if args.first_improvement {
+                Strategy::FirstImprovement
+            } else {
+                Strategy::SteepestAscent
+            },
+            max_transfer: args.polish_max_transfer,
+            tabu_tenure: args.tabu_tenure,
+            n_restarts: args.polish_restarts,
+            seed: config.seed,
+            ..Default::default()
+        };
+        for &start in res.iter().rev().take(args.polish) {
+            let polished = local_search::polish(start, &winners, &config);
+            println!(
+                "{} ({} wins) -> {} ({} wins), {:+.1} points",
+                precision.format_distribution(&polished.start),
+                polished.start_score.wins,
+                precision.format_distribution(&polished.best),
+                polished.best_score.wins,
+                polished.gain() as f32 / 2.0
+            );
+        }
+    }
+    if args.archetypes > 0 {
+        println!("Grouping the winners into {} archetypes", args.archetypes);
+        let config = ClusterConfig {
+            k: args.archetypes,
+            seed: config.seed,
+            ..Default::default()
+        };
+        print_archetypes(&winners, &config, precision);
+    }
+    let summary = metrics_layer.summary(start_time.elapsed());
+    println!(
+        "Ran {} battles in {:.1}s ({:.0} battles/s)",
+        summary.battles, summary.wall_time_secs, summary.battles_per_sec
+    );
+    for (phase, totals) in &summary.phases {
+        println!(
+            "  {:<20} {:>10} spans {:>12.3}s total",
+            phase, totals.count, totals.total_secs
+        );
+    }
+    if let Some(path) = &args.report {
+        write_report(path, &config, &winners, &res, Some(&summary));
+    }
+    if let Some(path) = &args.metrics_json {
+        let json = serde_json::to_string_pretty(&summary).expect("Could not serialize metrics");
+        std::fs::write(path, json).expect("Could not write the metrics file");
+    }
 }
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::span;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// How many one-on-one battles have been run by this process
+static BATTLES: AtomicU64 = AtomicU64::new(0);
+
+/// add_battles records that `n` more battles have been run. Callers add a whole round's
+/// worth at once, rather than counting inside `core::battle`, to keep the hot loop free
+/// of shared writes.
+pub fn add_battles(n: u64) {
+    BATTLES.fetch_add(n, Ordering::Relaxed);
+}
+
+/// battles gives how many battles have been run by this process so far
+pub fn battles() -> u64 {
+    BATTLES.load(Ordering::Relaxed)
+}
+
+/// Time spent in every span with a given name
+#[derive(Debug, Default, Clone, Copy)]
+struct PhaseTotals {
+    count: u64,
+    total: Duration,
+}
+
+/// MetricsLayer is a tracing layer that adds up how long every span was open, grouped by
+/// span name. Spans running in parallel on different rayon workers all add to the same
+/// total, so for a parallel phase the total is the time summed across workers.
+#[derive(Debug, Default, Clone)]
+pub struct MetricsLayer {
+    phases: Arc<Mutex<BTreeMap<&'static str, PhaseTotals>>>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        MetricsLayer::default()
+    }
+
+    /// summary gives the battle count and time per phase since the start of the run
+    pub fn summary(&self, wall_time: Duration) -> MetricsSummary {
+        let wall_time_secs = wall_time.as_secs_f64();
+        let battles = battles();
+        let phases = self
+            .phases
+            .lock()
+            .expect("Metrics lock was poisoned")
+            .iter()
+            .map(|(name, totals)| {
+                let total_secs = totals.total.as_secs_f64();
+                (
+                    name.to_string(),
+                    PhaseSummary {
+                        count: totals.count,
+                        total_secs,
+                        mean_secs: total_secs / totals.count as f64,
+                    },
+                )
+            })
+            .collect();
+        MetricsSummary {
+            version: env!("CARGO_PKG_VERSION"),
+            wall_time_secs,
+            battles,
+            battles_per_sec: battles as f64 / wall_time_secs,
+            phases,
+        }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Instant::now());
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span.extensions().get::<Instant>().map(|t| t.elapsed()) else {
+            return;
+        };
+        let mut phases = self.phases.lock().expect("Metrics lock was poisoned");
+        let totals = phases.entry(span.name()).or_default();
+        totals.count += 1;
+        totals.total += elapsed;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseSummary {
+    /// How many spans with this name were closed
+    pub count: u64,
+    /// Time spent in all of them together
+    pub total_secs: f64,
+    pub mean_secs: f64,
+}
+
+/// A summary of a run, to compare performance between versions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSummary {
+    pub version: &'static str,
+    pub wall_time_secs: f64,
+    pub battles: u64,
+    pub battles_per_sec: f64,
+    pub phases: BTreeMap<String, PhaseSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_phase_totals() {
+        let layer = MetricsLayer::new();
+        let subscriber = tracing_subscriber::registry().with(layer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                let _span = tracing::info_span!("phase_a").entered();
+            }
+            let _span = tracing::info_span!("phase_b").entered();
+        });
+
+        let got = layer.summary(Duration::from_secs(1));
+        assert_eq!(3, got.phases["phase_a"].count);
+        assert_eq!(1, got.phases["phase_b"].count);
+        assert_eq!(2, got.phases.len());
+    }
+}
@@ -0,0 +1,432 @@
+//! Noisy deployment, where the troops that actually arrive at each castle are not quite
+//! the ones that were sent. When each castle's noise is independent of the others, the
+//! chances of winning a battle are worked out exactly, and otherwise they are estimated
+//! by Monte Carlo.
+
+use std::borrow::Cow;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::core::{self, N_CASTLES, N_TROOPS};
+use crate::metrics;
+
+/// The most random draws `NoisyBattle::monte_carlo_draws` should allow for one job, about
+/// ten seconds on one core
+pub const MAX_MONTE_CARLO_DRAWS: u64 = 2_000_000_000;
+
+/// Twice the points of every castle together, so that a battle's score, in half points,
+/// is a whole number between 0 and this
+const TOTAL_HALF_POINTS: usize = N_CASTLES * (N_CASTLES + 1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum NoiseModel {
+    /// Each troop arrives with probability `survival`, and is lost otherwise. Allocations
+    /// in units of a `fixed_point::Precision` draw each unit on its own, so the finer the
+    /// precision, the less the share that arrives varies.
+    Binomial { survival: f64 },
+    /// Each castle gets between `k` fewer and `k` more troops than were sent, uniformly,
+    /// but never fewer than 0. `k` is in the same units as the allocations.
+    Jitter { k: i16 },
+    /// Each troop goes to a uniformly random castle instead, with probability
+    /// `probability`. The castles are no longer independent, so this is only ever
+    /// estimated by Monte Carlo.
+    Misroute { probability: f64 },
+}
+
+impl NoiseModel {
+    /// apply draws the troops that actually arrive at each castle
+    pub fn apply<R: Rng>(&self, allocation: &[i16; N_CASTLES], rng: &mut R) -> [i16; N_CASTLES] {
+        match *self {
+            NoiseModel::Binomial { survival } => {
+                allocation.map(|t| (0..t).filter(|_| rng.gen_bool(survival)).count() as i16)
+            }
+            NoiseModel::Jitter { k } => allocation.map(|t| (t + rng.gen_range(-k..=k)).max(0)),
+            NoiseModel::Misroute { probability } => {
+                let mut arrived = *allocation;
+                for (c, &t) in allocation.iter().enumerate() {
+                    for _ in 0..t {
+                        if rng.gen_bool(probability) {
+                            arrived[c] -= 1;
+                            arrived[rng.gen_range(0..N_CASTLES)] += 1;
+                        }
+                    }
+                }
+                arrived
+            }
+        }
+    }
+
+    /// castle_distribution gives the chance of each number of troops arriving at a castle
+    /// that `troops` were sent to, indexed by the number that arrive. Returns `None` if
+    /// the castles are not independent.
+    pub fn castle_distribution(&self, troops: i16) -> Option<Vec<f64>> {
+        let troops = troops.max(0) as usize;
+        match *self {
+            NoiseModel::Binomial { survival } => Some(binomial_pmf(troops, survival)),
+            NoiseModel::Jitter { k } => {
+                let k = k.max(0) as usize;
+                let mut pmf = vec![0.0; troops + k + 1];
+                let p = 1.0 / (2 * k + 1) as f64;
+                for shift in 0..=2 * k {
+                    // Anything that would go below 0 arrives as 0
+                    pmf[(troops + shift).saturating_sub(k)] += p;
+                }
+                Some(pmf)
+            }
+            NoiseModel::Misroute { .. } => None,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, NoiseModel::Misroute { .. })
+    }
+}
+
+/// The chance of each number of successes out of `n` tries that each succeed with
+/// probability `p`. It is worked out in log space, with
+/// `p[k + 1] = p[k] * (n - k) / (k + 1) * p / (1 - p)`, so that neither the binomial
+/// coefficients nor the powers overflow or underflow for thousands of tries.
+fn binomial_pmf(n: usize, p: f64) -> Vec<f64> {
+    let mut pmf = vec![0.0; n + 1];
+    if p <= 0.0 {
+        pmf[0] = 1.0;
+        return pmf;
+    }
+    if p >= 1.0 {
+        pmf[n] = 1.0;
+        return pmf;
+    }
+    let log_odds = p.ln() - (1.0 - p).ln();
+    let mut log_p = n as f64 * (1.0 - p).ln();
+    for (k, chance) in pmf.iter_mut().enumerate() {
+        *chance = log_p.exp();
+        log_p += ((n - k) as f64).ln() - ((k + 1) as f64).ln() + log_odds;
+    }
+    pmf
+}
+
+/// How a noisy battle is expected to go, from the first player's side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct NoisyOutcome {
+    pub p_win: f64,
+    pub p_tie: f64,
+    pub p_loss: f64,
+    pub expected_points: f64,
+    pub expected_opponent_points: f64,
+}
+
+/// A candidate's expected record against a field, under noise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct NoisyScore {
+    pub expected_wins: f64,
+    pub expected_ties: f64,
+    pub expected_losses: f64,
+}
+
+impl NoisyScore {
+    /// Expected wins plus half the expected ties
+    pub fn expected_victory_points(&self) -> f64 {
+        self.expected_wins + self.expected_ties / 2.0
+    }
+}
+
+/// Plays battles under a noise model. The per-castle distributions are worked out once,
+/// up front, for every number of troops that is sent.
+#[derive(Debug, Clone)]
+pub struct NoisyBattle {
+    model: NoiseModel,
+    /// How many battles to simulate when the outcome cannot be worked out exactly
+    n_samples: usize,
+    /// Every Monte Carlo estimate uses an RNG seeded with this, so that different
+    /// allocations are compared on the same random draws
+    seed: u64,
+    /// For each number of troops sent, the chance that at most each number arrives
+    cdfs: Option<FxHashMap<i16, Vec<f64>>>,
+}
+
+impl NoisyBattle {
+    pub fn new(model: NoiseModel, n_samples: usize, seed: u64) -> Self {
+        Self::with_troop_counts(model, n_samples, seed, 0..=N_TROOPS)
+    }
+
+    /// for_allocations is the same as `new`, but works out the distributions for the
+    /// troop counts in `allocations` rather than for every count up to `N_TROOPS`. Use it
+    /// for allocations in the units of a `fixed_point::Precision`, where the budget is too
+    /// big to work out every count.
+    pub fn for_allocations(
+        model: NoiseModel,
+        n_samples: usize,
+        seed: u64,
+        allocations: &[[i16; N_CASTLES]],
+    ) -> Self {
+        let counts = allocations.iter().flatten().copied();
+        Self::with_troop_counts(model, n_samples, seed, counts)
+    }
+
+    fn with_troop_counts(
+        model: NoiseModel,
+        n_samples: usize,
+        seed: u64,
+        counts: impl IntoIterator<Item = i16>,
+    ) -> Self {
+        let cdfs = model.is_exact().then(|| {
+            let mut cdfs: FxHashMap<i16, Vec<f64>> = FxHashMap::default();
+            for troops in counts {
+                cdfs.entry(troops).or_insert_with(|| {
+                    cumulative(
+                        &model
+                            .castle_distribution(troops)
+                            .expect("The model is exact"),
+                    )
+                });
+            }
+            cdfs
+        });
+        NoisyBattle {
+            model,
+            n_samples: n_samples.max(1),
+            seed,
+            cdfs,
+        }
+    }
+
+    /// The cumulative distribution for `troops` sent, from the table, unless it was not
+    /// worked out up front
+    fn cdf<'a>(&self, cdfs: &'a FxHashMap<i16, Vec<f64>>, troops: i16) -> Cow<'a, [f64]> {
+        match cdfs.get(&troops) {
+            Some(cdf) => Cow::Borrowed(cdf),
+            None => Cow::Owned(cumulative(
+                &self
+                    .model
+                    .castle_distribution(troops)
+                    .expect("The model is exact"),
+            )),
+        }
+    }
+
+    /// monte_carlo_draws estimates how many random draws scoring `n_candidates` against
+    /// a field of `n_field` allocations of `budget` troops takes. It is 0 when the model
+    /// is worked out exactly.
+    pub fn monte_carlo_draws(&self, n_candidates: usize, n_field: usize, budget: i16) -> u64 {
+        if self.cdfs.is_some() {
+            return 0;
+        }
+        // Every troop of both players is drawn in every sample
+        (n_candidates as u64)
+            .saturating_mul(n_field as u64)
+            .saturating_mul(self.n_samples as u64)
+            .saturating_mul(2 * budget.max(0) as u64)
+    }
+
+    /// outcome works out how `p1` against `p2` is expected to go under the noise, exactly
+    /// if the model allows it, and by Monte Carlo otherwise
+    pub fn outcome(&self, p1: &[i16; N_CASTLES], p2: &[i16; N_CASTLES]) -> NoisyOutcome {
+        match &self.cdfs {
+            Some(cdfs) => self.exact(cdfs, p1, p2),
+            None => self.monte_carlo(p1, p2),
+        }
+    }
+
+    /// exact treats each castle separately, and then adds up the distribution of the
+    /// total score castle by castle, in half points
+    fn exact(
+        &self,
+        cdfs: &FxHashMap<i16, Vec<f64>>,
+        p1: &[i16; N_CASTLES],
+        p2: &[i16; N_CASTLES],
+    ) -> NoisyOutcome {
+        let mut score = vec![0.0; TOTAL_HALF_POINTS + 1];
+        score[0] = 1.0;
+        let mut outcome = NoisyOutcome::default();
+        for c in 0..N_CASTLES {
+            let (p_win, p_tie) = castle_chances(&self.cdf(cdfs, p1[c]), &self.cdf(cdfs, p2[c]));
+            let p_loss = (1.0 - p_win - p_tie).max(0.0);
+            let value = c + 1;
+            outcome.expected_points += value as f64 * (p_win + p_tie / 2.0);
+            outcome.expected_opponent_points += value as f64 * (p_loss + p_tie / 2.0);
+
+            let mut next = vec![0.0; TOTAL_HALF_POINTS + 1];
+            for (half_points, &p) in score.iter().enumerate().filter(|(_, &p)| p > 0.0) {
+                next[half_points] += p * p_loss;
+                next[half_points + value] += p * p_tie;
+                next[half_points + 2 * value] += p * p_win;
+            }
+            score = next;
+        }
+        let half = TOTAL_HALF_POINTS / 2;
+        outcome.p_loss = score[..half].iter().sum();
+        outcome.p_tie = score[half];
+        outcome.p_win = score[half + 1..].iter().sum();
+        outcome
+    }
+
+    /// monte_carlo estimates the outcome from `n_samples` simulated battles
+    fn monte_carlo(&self, p1: &[i16; N_CASTLES], p2: &[i16; N_CASTLES]) -> NoisyOutcome {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut outcome = NoisyOutcome::default();
+        for _ in 0..self.n_samples {
+            let (s1, s2) = core::battle(
+                self.model.apply(p1, &mut rng),
+                self.model.apply(p2, &mut rng),
+            );
+            outcome.expected_points += s1 as f64;
+            outcome.expected_opponent_points += s2 as f64;
+            match s1.total_cmp(&s2) {
+                std::cmp::Ordering::Greater => outcome.p_win += 1.0,
+                std::cmp::Ordering::Less => outcome.p_loss += 1.0,
+                std::cmp::Ordering::Equal => outcome.p_tie += 1.0,
+            }
+        }
+        let n = self.n_samples as f64;
+        NoisyOutcome {
+            p_win: outcome.p_win / n,
+            p_tie: outcome.p_tie / n,
+            p_loss: outcome.p_loss / n,
+            expected_points: outcome.expected_points / n,
+            expected_opponent_points: outcome.expected_opponent_points / n,
+        }
+    }
+
+    /// score_against_field adds up `candidate`'s chances against every member of `field`
+    pub fn score_against_field(
+        &self,
+        candidate: &[i16; N_CASTLES],
+        field: &[[i16; N_CASTLES]],
+    ) -> NoisyScore {
+        let samples_per_battle = if self.cdfs.is_some() {
+            1
+        } else {
+            self.n_samples
+        };
+        metrics::add_battles((field.len() * samples_per_battle) as u64);
+        field
+            .par_iter()
+            .map(|opponent| self.outcome(candidate, opponent))
+            .fold(NoisyScore::default, |mut score, o| {
+                score.expected_wins += o.p_win;
+                score.expected_ties += o.p_tie;
+                score.expected_losses += o.p_loss;
+                score
+            })
+            .reduce(NoisyScore::default, |a, b| NoisyScore {
+                expected_wins: a.expected_wins + b.expected_wins,
+                expected_ties: a.expected_ties + b.expected_ties,
+                expected_losses: a.expected_losses + b.expected_losses,
+            })
+    }
+}
+
+fn cumulative(pmf: &[f64]) -> Vec<f64> {
+    pmf.iter()
+        .scan(0.0, |total, p| {
+            *total += p;
+            Some(*total)
+        })
+        .collect()
+}
+
+/// The chances that the first of two independent troop counts is bigger than the
+/// second, and that they are equal, from their cumulative distributions
+fn castle_chances(cdf1: &[f64], cdf2: &[f64]) -> (f64, f64) {
+    let at_most = |cdf: &[f64], n: isize| -> f64 {
+        if n < 0 {
+            0.0
+        } else {
+            cdf.get(n as usize).copied().unwrap_or(1.0)
+        }
+    };
+    let mut p_win = 0.0;
+    let mut p_tie = 0.0;
+    for n in 0..cdf2.len() as isize {
+        let p2 = at_most(cdf2, n) - at_most(cdf2, n - 1);
+        p_win += p2 * (1.0 - at_most(cdf1, n));
+        p_tie += p2 * (at_most(cdf1, n) - at_most(cdf1, n - 1));
+    }
+    (p_win, p_tie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_point::Precision;
+
+    #[test]
+    fn test_castle_distribution() {
+        let binomial = NoiseModel::Binomial { survival: 0.5 };
+        let pmf = binomial.castle_distribution(2).unwrap();
+        assert_eq!(vec![0.25, 0.5, 0.25], pmf);
+
+        let jitter = NoiseModel::Jitter { k: 2 };
+        let pmf = jitter.castle_distribution(1).unwrap();
+        assert_eq!(vec![0.4, 0.2, 0.2, 0.2], pmf);
+        assert!(NoiseModel::Misroute { probability: 0.1 }
+            .castle_distribution(1)
+            .is_none());
+
+        // Thousands of troops neither overflow nor underflow
+        let pmf = NoiseModel::Binomial { survival: 0.9 }
+            .castle_distribution(10_000)
+            .unwrap();
+        assert!(pmf.iter().all(|p| p.is_finite()));
+        assert!((pmf.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(
+            Some(9_000),
+            (0..pmf.len()).max_by(|&a, &b| pmf[a].total_cmp(&pmf[b]))
+        );
+
+        // 1 or 2 arrive against exactly 1
+        let (p_win, p_tie) =
+            castle_chances(&cumulative(&[0.0, 0.5, 0.5]), &cumulative(&[0.0, 1.0]));
+        assert_eq!((0.5, 0.5), (p_win, p_tie));
+    }
+
+    #[test]
+    fn test_exact_matches_monte_carlo() {
+        let p1: [i16; 10] = [0, 0, 0, 0, 0, 20, 20, 20, 20, 20];
+        let p2: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        for model in [
+            NoiseModel::Binomial { survival: 0.8 },
+            NoiseModel::Jitter { k: 3 },
+        ] {
+            let exact = NoisyBattle::new(model, 1, 0).outcome(&p1, &p2);
+            assert!((exact.p_win + exact.p_tie + exact.p_loss - 1.0).abs() < 1e-9);
+            assert!((exact.expected_points + exact.expected_opponent_points - 55.0).abs() < 1e-9);
+
+            let estimate = NoisyBattle {
+                cdfs: None,
+                ..NoisyBattle::new(model, 20_000, 3)
+            }
+            .outcome(&p1, &p2);
+            assert!((exact.p_win - estimate.p_win).abs() < 0.02);
+            assert!((exact.expected_points - estimate.expected_points).abs() < 0.2);
+        }
+
+        // In hundredths of a troop, from the troop counts that are actually sent
+        let hundredths = Precision::new(2);
+        let h1 = p1.map(|t| t * hundredths.scale());
+        let h2 = p2.map(|t| t * hundredths.scale());
+        let exact =
+            NoisyBattle::for_allocations(NoiseModel::Binomial { survival: 0.9 }, 1, 0, &[h1, h2])
+                .outcome(&h1, &h2);
+        assert!((exact.p_win + exact.p_tie + exact.p_loss - 1.0).abs() < 1e-9);
+        assert!(exact.p_win > 0.999);
+        assert!((exact.expected_points - 40.0).abs() < 1e-3);
+
+        // Without any noise, the result is certain
+        let noiseless = NoisyBattle::new(NoiseModel::Jitter { k: 0 }, 1, 0);
+        assert_eq!(1.0, noiseless.outcome(&p1, &p2).p_win);
+        let score = noiseless.score_against_field(&p2, &[p1, p2]);
+        assert_eq!(
+            (0.0, 1.0, 1.0),
+            (
+                score.expected_wins,
+                score.expected_ties,
+                score.expected_losses
+            )
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// How often to print a progress line when stdout is not a terminal
+const LOG_EVERY: Duration = Duration::from_secs(10);
+
+/// What the total of a `Progress` is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Tournaments,
+    Rounds,
+}
+
+impl Unit {
+    fn name(&self) -> &'static str {
+        match self {
+            Unit::Tournaments => "tournaments",
+            Unit::Rounds => "elimination rounds",
+        }
+    }
+}
+
+enum Reporter {
+    /// A progress bar, for when a person is watching
+    Bar(ProgressBar),
+    /// Periodic lines of text, for when stdout is going to a file or a pipe
+    Log { last_logged: Mutex<Instant> },
+}
+
+/// Progress counts finished tournaments and elimination rounds. It can be shared between
+/// rayon workers, which all report to the same counters. If stdout is a terminal it draws
+/// a progress bar with the throughput and ETA, otherwise it prints the same information
+/// every `LOG_EVERY`.
+pub struct Progress {
+    unit: Unit,
+    total: u64,
+    /// How much was already done before this `Progress` was created, e.g. when resuming
+    /// from a checkpoint. Left out of the throughput.
+    starting_position: u64,
+    tournaments: AtomicU64,
+    rounds: AtomicU64,
+    start_time: Instant,
+    reporter: Reporter,
+}
+
+impl Progress {
+    /// Start tracking `total` of `unit`, of which `done` are already finished
+    pub fn new(unit: Unit, total: u64, done: u64) -> Self {
+        let reporter = if std::io::stdout().is_terminal() {
+            let bar = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stdout())
+                .with_style(
+                    ProgressStyle::with_template(
+                        "[{elapsed_precise}] {wide_bar} {pos}/{len} {msg} ({per_sec}, ETA {eta})",
+                    )
+                    .expect("Progress bar template is invalid"),
+                )
+                .with_position(done);
+            bar.reset_eta();
+            Reporter::Bar(bar)
+        } else {
+            Reporter::Log {
+                last_logged: Mutex::new(Instant::now()),
+            }
+        };
+        let (tournaments, rounds) = match unit {
+            Unit::Tournaments => (done, 0),
+            Unit::Rounds => (0, done),
+        };
+        Progress {
+            unit,
+            total,
+            starting_position: done,
+            tournaments: AtomicU64::new(tournaments),
+            rounds: AtomicU64::new(rounds),
+            start_time: Instant::now(),
+            reporter,
+        }
+    }
+
+    pub fn tournaments(&self) -> u64 {
+        self.tournaments.load(Ordering::Relaxed)
+    }
+
+    pub fn rounds(&self) -> u64 {
+        self.rounds.load(Ordering::Relaxed)
+    }
+
+    /// Record that one more tournament has finished
+    pub fn tournament_finished(&self) {
+        self.tournaments.fetch_add(1, Ordering::Relaxed);
+        self.update(Unit::Tournaments);
+    }
+
+    /// Record that one more elimination round has finished
+    pub fn round_finished(&self) {
+        self.rounds.fetch_add(1, Ordering::Relaxed);
+        self.update(Unit::Rounds);
+    }
+
+    fn position(&self) -> u64 {
+        match self.unit {
+            Unit::Tournaments => self.tournaments(),
+            Unit::Rounds => self.rounds(),
+        }
+    }
+
+    /// Text shown after the main count, including the round count while counting
+    /// tournaments
+    fn message(&self) -> String {
+        match self.unit {
+            Unit::Tournaments => {
+                format!("{}, {} elimination rounds", self.unit.name(), self.rounds())
+            }
+            Unit::Rounds => self.unit.name().to_string(),
+        }
+    }
+
+    fn update(&self, changed: Unit) {
+        match &self.reporter {
+            Reporter::Bar(bar) => {
+                if changed == self.unit {
+                    bar.set_message(self.message());
+                    bar.set_position(self.position());
+                }
+            }
+            Reporter::Log { last_logged } => {
+                // Only one worker needs to do the logging, the rest can carry on
+                let Ok(mut last_logged) = last_logged.try_lock() else {
+                    return;
+                };
+                if last_logged.elapsed() >= LOG_EVERY {
+                    *last_logged = Instant::now();
+                    println!("{}", self.log_line());
+                }
+            }
+        }
+    }
+
+    fn log_line(&self) -> String {
+        let position = self.position();
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let per_sec = (position - self.starting_position) as f64 / elapsed;
+        let eta = if per_sec > 0.0 {
+            format!(
+                "{:.0}s",
+                self.total.saturating_sub(position) as f64 / per_sec
+            )
+        } else {
+            "unknown".to_string()
+        };
+        format!(
+            "Finished {}/{} {} ({:.1}/s, ETA {})",
+            position,
+            self.total,
+            self.message(),
+            per_sec,
+            eta
+        )
+    }
+
+    /// Stop the progress bar, or print a last line
+    pub fn finish(&self) {
+        match &self.reporter {
+            Reporter::Bar(bar) => {
+                bar.set_message(self.message());
+                bar.finish();
+                // Leave the finished bar on its own line
+                println!();
+            }
+            Reporter::Log { .. } => println!("{}", self.log_line()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_counts_across_workers() {
+        let progress = Progress::new(Unit::Tournaments, 200, 100);
+        (0..100).into_par_iter().for_each(|_| {
+            progress.round_finished();
+            progress.round_finished();
+            progress.tournament_finished();
+        });
+        assert_eq!(200, progress.tournaments());
+        assert_eq!(200, progress.rounds());
+    }
+}
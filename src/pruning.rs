@@ -0,0 +1,165 @@
+//! Shrinking a set of candidate strategies before running tournaments or building a
+//! `PayoffMatrix`, by comparing their results against a field. The candidates can be any
+//! allocations, like `[i16; 10]` or the `Vec<i16>` of `enumerate`.
+
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+use crate::core;
+use crate::metrics;
+
+/// What was left after pruning, and how much was removed at each step
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pruned<S> {
+    /// One candidate from each equivalence class that is not weakly dominated, in the
+    /// order they were given
+    pub kept: Vec<S>,
+    /// How many candidates each kept one stands for, itself included
+    pub class_sizes: Vec<usize>,
+    pub n_candidates: usize,
+    /// How many were left after collapsing equivalent candidates
+    pub n_distinct: usize,
+    /// How many were left after removing weakly dominated ones
+    pub n_undominated: usize,
+}
+
+impl<S> Pruned<S> {
+    /// The fraction of candidates that were removed
+    pub fn reduction(&self) -> f64 {
+        if self.n_candidates == 0 {
+            return 0.0;
+        }
+        1.0 - self.n_undominated as f64 / self.n_candidates as f64
+    }
+}
+
+/// outcomes gives `candidate`'s result against each member of `field`: 1 for a win, 0
+/// for a tie and -1 for a loss
+pub fn outcomes<S: AsRef<[i16]>>(candidate: &S, field: &[S]) -> Vec<i8> {
+    field
+        .iter()
+        .map(|opponent| {
+            let (c_score, o_score) = core::battle_slices(candidate.as_ref(), opponent.as_ref());
+            match c_score.total_cmp(&o_score) {
+                Ordering::Greater => 1,
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+            }
+        })
+        .collect()
+}
+
+/// Whether `a` does at least as well as `b` against every opponent, and better against
+/// at least one
+fn weakly_dominates(a: &[i8], b: &[i8]) -> bool {
+    let mut strictly = false;
+    for (x, y) in a.iter().zip(b) {
+        match x.cmp(y) {
+            Ordering::Less => return false,
+            Ordering::Greater => strictly = true,
+            Ordering::Equal => {}
+        }
+    }
+    strictly
+}
+
+/// prune first collapses candidates that get exactly the same result against every member
+/// of `field` into one, and then removes candidates that are weakly dominated against
+/// `field` by another one. How much this removes depends on the field: the bigger and
+/// more varied it is, the fewer candidates look alike.
+pub fn prune<S: AsRef<[i16]> + Clone + Sync>(candidates: &[S], field: &[S]) -> Pruned<S> {
+    let _span = tracing::info_span!("pruning", n_candidates = candidates.len()).entered();
+    metrics::add_battles((candidates.len() * field.len()) as u64);
+    let all_outcomes: Vec<Vec<i8>> = candidates.par_iter().map(|c| outcomes(c, field)).collect();
+
+    // Equivalence classes, keeping the first candidate of each
+    let mut class_of: FxHashMap<&[i8], usize> = FxHashMap::default();
+    let mut representatives: Vec<usize> = Vec::new();
+    let mut class_sizes: Vec<usize> = Vec::new();
+    for (idx, o) in all_outcomes.iter().enumerate() {
+        let class = *class_of.entry(o.as_slice()).or_insert_with(|| {
+            representatives.push(idx);
+            class_sizes.push(0);
+            representatives.len() - 1
+        });
+        class_sizes[class] += 1;
+    }
+    let n_distinct = representatives.len();
+
+    // Only a candidate with a higher total can weakly dominate another, so each one only
+    // has to be checked against those
+    let totals: Vec<i64> = representatives
+        .iter()
+        .map(|&idx| all_outcomes[idx].iter().map(|&o| o as i64).sum())
+        .collect();
+    let undominated: Vec<bool> = (0..n_distinct)
+        .into_par_iter()
+        .map(|i| {
+            !(0..n_distinct).any(|j| {
+                totals[j] > totals[i]
+                    && weakly_dominates(
+                        &all_outcomes[representatives[j]],
+                        &all_outcomes[representatives[i]],
+                    )
+            })
+        })
+        .collect();
+
+    let mut kept = Vec::new();
+    let mut kept_sizes = Vec::new();
+    for (class, &idx) in representatives.iter().enumerate() {
+        if undominated[class] {
+            kept.push(candidates[idx].clone());
+            kept_sizes.push(class_sizes[class]);
+        }
+    }
+    Pruned {
+        n_candidates: candidates.len(),
+        n_distinct,
+        n_undominated: kept.len(),
+        kept,
+        class_sizes: kept_sizes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weakly_dominates() {
+        assert!(weakly_dominates(&[1, 0, 1], &[1, 0, 0]));
+        assert!(!weakly_dominates(&[1, 0, 0], &[1, 0, 0]));
+        assert!(!weakly_dominates(&[1, -1, 1], &[0, 0, 0]));
+    }
+
+    #[test]
+    fn test_prune() {
+        let field: Vec<[i16; 10]> = vec![
+            [10, 10, 10, 10, 10, 10, 10, 10, 10, 10],
+            [0, 0, 0, 0, 0, 20, 20, 20, 20, 20],
+        ];
+        let strong: [i16; 10] = [0, 0, 0, 0, 11, 21, 21, 21, 21, 5];
+        let weak: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        // Loses to both, like `weak`
+        let also_weak: [i16; 10] = [0, 100, 0, 0, 0, 0, 0, 0, 0, 0];
+        let candidates = vec![weak, strong, also_weak, strong];
+        assert_eq!(vec![1, 1], outcomes(&strong, &field));
+        assert_eq!(vec![-1, -1], outcomes(&weak, &field));
+
+        let pruned = prune(&candidates, &field);
+        assert_eq!(4, pruned.n_candidates);
+        assert_eq!(2, pruned.n_distinct);
+        assert_eq!(1, pruned.n_undominated);
+        assert_eq!(vec![strong], pruned.kept);
+        assert_eq!(vec![2], pruned.class_sizes);
+        assert_eq!(0.75, pruned.reduction());
+
+        // Works on the `Vec` allocations of smaller variants too
+        let candidates: Vec<Vec<i16>> = vec![vec![3, 0], vec![0, 3], vec![1, 2]];
+        let pruned = prune(&candidates, &candidates);
+        assert_eq!(vec![vec![0, 3]], pruned.kept);
+    }
+}
@@ -0,0 +1,213 @@
+//! Python bindings, built with maturin (`maturin develop --release`). Allocations are
+//! passed around as `(n, 10)` NumPy arrays of `int16`, which are read in place rather
+//! than being copied into Python lists.
+
+use numpy::ndarray::{Array2, ArrayView1, ArrayView2};
+use numpy::{IntoPyArray, PyArray2, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use rustc_hash::FxHashSet;
+
+use crate::core::{self, N_CASTLES};
+use crate::final_battle;
+use crate::seventh_battle::{self, Bracket};
+
+/// A match from a double elimination bracket:
+/// `(bracket, round, p1, p2, p1_score, p2_score, winner)`
+type MatchTuple = (
+    &'static str,
+    usize,
+    [i16; N_CASTLES],
+    [i16; N_CASTLES],
+    f32,
+    f32,
+    [i16; N_CASTLES],
+);
+
+fn row_to_distribution(row: ArrayView1<'_, i16>) -> [i16; N_CASTLES] {
+    let mut result = [0_i16; N_CASTLES];
+    for (r, &troops) in result.iter_mut().zip(row.iter()) {
+        *r = troops;
+    }
+    result
+}
+
+fn check_shape(arr: &ArrayView2<'_, i16>, name: &str) -> PyResult<()> {
+    if arr.ncols() != N_CASTLES {
+        return Err(PyValueError::new_err(format!(
+            "{name} should have shape (n, {N_CASTLES}), got {:?}",
+            arr.shape()
+        )));
+    }
+    Ok(())
+}
+
+fn to_distributions(arr: ArrayView2<'_, i16>, name: &str) -> PyResult<Vec<[i16; N_CASTLES]>> {
+    check_shape(&arr, name)?;
+    Ok(arr.rows().into_iter().map(row_to_distribution).collect())
+}
+
+fn to_array2(players: &[[i16; N_CASTLES]]) -> Array2<i16> {
+    Array2::from_shape_vec(
+        (players.len(), N_CASTLES),
+        players.iter().flatten().copied().collect(),
+    )
+    .expect("Every player has N_CASTLES castles")
+}
+
+fn check_bracket_size(n: usize) -> PyResult<()> {
+    if n < 2 || !n.is_power_of_two() {
+        return Err(PyValueError::new_err(format!(
+            "Brackets need a power of 2 players, got {n}"
+        )));
+    }
+    Ok(())
+}
+
+/// Compare two allocations, and return both players' scores
+#[pyfunction]
+fn battle(p1: [i16; N_CASTLES], p2: [i16; N_CASTLES]) -> (f32, f32) {
+    core::battle(p1, p2)
+}
+
+/// Battle row `i` of `p1s` against row `i` of `p2s`, for every row. Returns an `(n, 2)`
+/// array of scores.
+#[pyfunction]
+fn battle_many<'py>(
+    py: Python<'py>,
+    p1s: PyReadonlyArray2<'py, i16>,
+    p2s: PyReadonlyArray2<'py, i16>,
+) -> PyResult<Bound<'py, PyArray2<f32>>> {
+    let p1s = p1s.as_array();
+    let p2s = p2s.as_array();
+    check_shape(&p1s, "p1s")?;
+    check_shape(&p2s, "p2s")?;
+    if p1s.nrows() != p2s.nrows() {
+        return Err(PyValueError::new_err(format!(
+            "p1s and p2s have a different number of rows: {} and {}",
+            p1s.nrows(),
+            p2s.nrows()
+        )));
+    }
+
+    let scores: Vec<f32> = py.detach(|| {
+        (0..p1s.nrows())
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                let (s1, s2) = core::battle(
+                    row_to_distribution(p1s.row(i)),
+                    row_to_distribution(p2s.row(i)),
+                );
+                [s1, s2]
+            })
+            .collect()
+    });
+    let scores = Array2::from_shape_vec((p1s.nrows(), 2), scores)
+        .expect("There are two scores for every row");
+    Ok(scores.into_pyarray(py))
+}
+
+/// Generate `n` uniform random allocations as an `(n, 10)` array
+#[pyfunction]
+#[pyo3(signature = (n, seed=None))]
+fn generate_uniform_random_distributions(
+    py: Python<'_>,
+    n: usize,
+    seed: Option<u64>,
+) -> Bound<'_, PyArray2<i16>> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let players: Vec<[i16; N_CASTLES]> = (0..n)
+        .map(|_| core::generate_uniform_random_distribution_with_rng(&mut rng))
+        .collect();
+    to_array2(&players).into_pyarray(py)
+}
+
+/// Play every player against every other. Returns the players sorted from fewest to
+/// most wins, and an `(n, 3)` array of their wins, ties and losses.
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn run_battles<'py>(
+    py: Python<'py>,
+    players: PyReadonlyArray2<'py, i16>,
+) -> PyResult<(Bound<'py, PyArray2<i16>>, Bound<'py, PyArray2<u32>>)> {
+    let players = to_distributions(players.as_array(), "players")?;
+    let results = py.detach(|| core::run_battles_slice(&players, None));
+
+    let sorted: Vec<[i16; N_CASTLES]> = results.iter().map(|(p, _)| *p).collect();
+    let scores: Vec<u32> = results
+        .iter()
+        .flat_map(|(_, bs)| [bs.wins, bs.ties, bs.losses])
+        .collect();
+    let scores = Array2::from_shape_vec((results.len(), 3), scores)
+        .expect("There are three counts for every player");
+    Ok((to_array2(&sorted).into_pyarray(py), scores.into_pyarray(py)))
+}
+
+/// Run `final_battle::tournament`. Returns the players from last place to first place.
+/// Duplicate players are merged into one.
+#[pyfunction]
+fn tournament<'py>(
+    py: Python<'py>,
+    players: PyReadonlyArray2<'py, i16>,
+) -> PyResult<Bound<'py, PyArray2<i16>>> {
+    let players = to_distributions(players.as_array(), "players")?;
+    let n_distinct = players.iter().collect::<FxHashSet<_>>().len();
+    if n_distinct < 2 {
+        return Err(PyValueError::new_err(
+            "A tournament needs at least 2 different players",
+        ));
+    }
+    let res = py.detach(|| final_battle::tournament(&players, false));
+    Ok(to_array2(&res).into_pyarray(py))
+}
+
+/// Run a single elimination bracket over `pool`, which should already be in bracket
+/// order. Returns the winner.
+#[pyfunction]
+fn single_elimination(pool: PyReadonlyArray2<'_, i16>) -> PyResult<[i16; N_CASTLES]> {
+    let pool = to_distributions(pool.as_array(), "pool")?;
+    check_bracket_size(pool.len())?;
+    Ok(seventh_battle::run_single_elimination(&pool)[0])
+}
+
+/// Run a double elimination bracket over `pool`, which should already be in bracket
+/// order. Returns every match as a `MatchTuple`. The winner of the last match is the
+/// champion.
+#[pyfunction]
+fn double_elimination(pool: PyReadonlyArray2<'_, i16>) -> PyResult<Vec<MatchTuple>> {
+    let pool = to_distributions(pool.as_array(), "pool")?;
+    check_bracket_size(pool.len())?;
+    Ok(seventh_battle::run_double_elimination(&pool)
+        .iter()
+        .map(|m| {
+            let bracket = match m.bracket {
+                Bracket::Winners => "winners",
+                Bracket::Losers => "losers",
+                Bracket::GrandFinal => "grand_final",
+            };
+            (
+                bracket, m.round, m.p1, m.p2, m.p1_score, m.p2_score, m.winner,
+            )
+        })
+        .collect())
+}
+
+#[pymodule]
+fn rs_battle_for_nation(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("N_CASTLES", N_CASTLES)?;
+    m.add("N_TROOPS", core::N_TROOPS)?;
+    m.add_function(wrap_pyfunction!(battle, m)?)?;
+    m.add_function(wrap_pyfunction!(battle_many, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_uniform_random_distributions, m)?)?;
+    m.add_function(wrap_pyfunction!(run_battles, m)?)?;
+    m.add_function(wrap_pyfunction!(tournament, m)?)?;
+    m.add_function(wrap_pyfunction!(single_elimination, m)?)?;
+    m.add_function(wrap_pyfunction!(double_elimination, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,281 @@
+//! Repeated matches, where the same two players battle over several rounds. Each player
+//! is a `core::Policy`, so it can change its allocation in response to what the other
+//! one did, and with attrition, the troops lost in one round are gone for the next.
+
+use serde::Serialize;
+
+use crate::core::{self, Policy, RoundState, N_CASTLES, N_TROOPS};
+use crate::metrics;
+use crate::report::Side;
+
+/// What happens to the troops between rounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Carryover {
+    /// Both players get their full budget back every round
+    Reset,
+    /// Troops sent to a castle that is lost are destroyed, and on a tie, each side loses
+    /// half of its troops there. The survivors, plus `reinforcements`, are the next
+    /// round's budget.
+    Attrition { reinforcements: i16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MatchConfig {
+    pub n_rounds: usize,
+    pub carryover: Carryover,
+    /// What each player starts with
+    pub budget: i16,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            n_rounds: 10,
+            carryover: Carryover::Reset,
+            budget: N_TROOPS,
+        }
+    }
+}
+
+/// One round of a match
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RoundRecord {
+    pub p1: [i16; N_CASTLES],
+    pub p2: [i16; N_CASTLES],
+    pub p1_budget: i16,
+    pub p2_budget: i16,
+    pub p1_score: f32,
+    pub p2_score: f32,
+    pub winner: Side,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchResult {
+    pub rounds: Vec<RoundRecord>,
+    pub p1_round_wins: u32,
+    pub p2_round_wins: u32,
+    pub ties: u32,
+    /// Points over every round
+    pub p1_total: f32,
+    pub p2_total: f32,
+}
+
+impl MatchResult {
+    /// Whoever won more rounds
+    pub fn winner(&self) -> Side {
+        match self.p1_round_wins.cmp(&self.p2_round_wins) {
+            std::cmp::Ordering::Greater => Side::P1,
+            std::cmp::Ordering::Less => Side::P2,
+            std::cmp::Ordering::Equal => Side::Tie,
+        }
+    }
+}
+
+/// How many troops of `own` survive a round against `other` with attrition
+fn survivors(own: &[i16; N_CASTLES], other: &[i16; N_CASTLES]) -> i16 {
+    own.iter()
+        .zip(other)
+        .map(|(&o, &t)| match o.cmp(&t) {
+            std::cmp::Ordering::Greater => o,
+            std::cmp::Ordering::Less => 0,
+            std::cmp::Ordering::Equal => o - o / 2,
+        })
+        .sum()
+}
+
+/// Panics unless `allocation` spends exactly `budget` troops, with no negative castles
+fn check_allocation(allocation: &[i16; N_CASTLES], budget: i16, player: &str) {
+    assert!(
+        core::is_valid_distribution_with_budget(allocation, budget),
+        "{player}'s policy allocated {allocation:?}, which does not spend its budget of {budget}"
+    );
+}
+
+/// play_match plays `config.n_rounds` rounds between two policies, and records every one
+pub fn play_match(p1: &mut dyn Policy, p2: &mut dyn Policy, config: &MatchConfig) -> MatchResult {
+    let _span = tracing::info_span!("repeated_match", n_rounds = config.n_rounds).entered();
+    metrics::add_battles(config.n_rounds as u64);
+    let mut p1_history: Vec<[i16; N_CASTLES]> = Vec::with_capacity(config.n_rounds);
+    let mut p2_history: Vec<[i16; N_CASTLES]> = Vec::with_capacity(config.n_rounds);
+    let mut result = MatchResult {
+        rounds: Vec::with_capacity(config.n_rounds),
+        p1_round_wins: 0,
+        p2_round_wins: 0,
+        ties: 0,
+        p1_total: 0.0,
+        p2_total: 0.0,
+    };
+    let (mut p1_budget, mut p2_budget) = (config.budget, config.budget);
+
+    for round in 0..config.n_rounds {
+        let p1_alloc = p1.allocate(&RoundState {
+            round,
+            budget: p1_budget,
+            own_history: &p1_history,
+            opponent_history: &p2_history,
+        });
+        let p2_alloc = p2.allocate(&RoundState {
+            round,
+            budget: p2_budget,
+            own_history: &p2_history,
+            opponent_history: &p1_history,
+        });
+        check_allocation(&p1_alloc, p1_budget, "Player 1");
+        check_allocation(&p2_alloc, p2_budget, "Player 2");
+
+        let (p1_score, p2_score) = core::battle(p1_alloc, p2_alloc);
+        let winner = match p1_score.total_cmp(&p2_score) {
+            std::cmp::Ordering::Greater => {
+                result.p1_round_wins += 1;
+                Side::P1
+            }
+            std::cmp::Ordering::Less => {
+                result.p2_round_wins += 1;
+                Side::P2
+            }
+            std::cmp::Ordering::Equal => {
+                result.ties += 1;
+                Side::Tie
+            }
+        };
+        result.p1_total += p1_score;
+        result.p2_total += p2_score;
+        result.rounds.push(RoundRecord {
+            p1: p1_alloc,
+            p2: p2_alloc,
+            p1_budget,
+            p2_budget,
+            p1_score,
+            p2_score,
+            winner,
+        });
+
+        if let Carryover::Attrition { reinforcements } = config.carryover {
+            // Negative reinforcements can take troops away, but never below none at all
+            p1_budget = survivors(&p1_alloc, &p2_alloc)
+                .saturating_add(reinforcements)
+                .max(0);
+            p2_budget = survivors(&p2_alloc, &p1_alloc)
+                .saturating_add(reinforcements)
+                .max(0);
+        }
+        p1_history.push(p1_alloc);
+        p2_history.push(p2_alloc);
+    }
+    result
+}
+
+/// Plays `opening` in the first round, and after that the cheapest allocation that wins
+/// the most points against the opponent's last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterLast {
+    pub opening: [i16; N_CASTLES],
+}
+
+impl Policy for CounterLast {
+    fn allocate(&mut self, state: &RoundState) -> [i16; N_CASTLES] {
+        let Some(last) = state.opponent_history.last() else {
+            return core::rescale(&self.opening, state.budget);
+        };
+        // With only 10 castles, every set of castles to take can be tried
+        let (_, _, castles) = (0_u32..1 << N_CASTLES)
+            .filter_map(|set| {
+                let cost: i32 = (0..N_CASTLES)
+                    .filter(|c| set & (1 << c) != 0)
+                    .map(|c| last[c] as i32 + 1)
+                    .sum();
+                let points: usize = (0..N_CASTLES)
+                    .filter(|c| set & (1 << c) != 0)
+                    .map(|c| c + 1)
+                    .sum();
+                (cost <= state.budget as i32).then_some((points, -cost, set))
+            })
+            .max()
+            .expect("Taking no castles is always affordable");
+        let mut allocation = [0_i16; N_CASTLES];
+        for (c, troops) in allocation.iter_mut().enumerate() {
+            if castles & (1 << c) != 0 {
+                *troops = last[c] + 1;
+            }
+        }
+        // Any troops left over go to the most valuable castle being taken
+        let top = (0..N_CASTLES)
+            .rev()
+            .find(|c| castles & (1 << c) != 0)
+            .unwrap_or(N_CASTLES - 1);
+        allocation[top] += state.budget - allocation.iter().sum::<i16>();
+        allocation
+    }
+}
+
+/// Plays `opening` in the first round, and after that whatever the opponent played last,
+/// rescaled to its own budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyLast {
+    pub opening: [i16; N_CASTLES],
+}
+
+impl Policy for CopyLast {
+    fn allocate(&mut self, state: &RoundState) -> [i16; N_CASTLES] {
+        let last = state.opponent_history.last().unwrap_or(&self.opening);
+        core::rescale(last, state.budget)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_match() {
+        let even: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let config = MatchConfig {
+            n_rounds: 4,
+            ..Default::default()
+        };
+        let mut counter = CounterLast { opening: even };
+        let res = play_match(&mut { even }, &mut counter, &config);
+        assert_eq!(4, res.rounds.len());
+        // The first round is a mirror match, and after that the counter wins every round
+        assert_eq!(Side::Tie, res.rounds[0].winner);
+        assert_eq!(1, res.ties);
+        assert_eq!(3, res.p2_round_wins);
+        assert_eq!(Side::P2, res.winner());
+        // It takes castles 2-10 with 11 troops each, and puts the last troop on castle 10
+        assert_eq!([0, 11, 11, 11, 11, 11, 11, 11, 11, 12], res.rounds[1].p2);
+    }
+
+    #[test]
+    fn test_attrition() {
+        let p1: [i16; 10] = [0, 0, 0, 0, 0, 20, 20, 20, 20, 20];
+        let p2: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let config = MatchConfig {
+            n_rounds: 3,
+            carryover: Carryover::Attrition { reinforcements: 5 },
+            ..Default::default()
+        };
+        let res = play_match(&mut { p1 }, &mut CopyLast { opening: p2 }, &config);
+        // p1 keeps everything at the castles it won, and p2 keeps only its troops at
+        // castles 1-5
+        assert_eq!(105, res.rounds[1].p1_budget);
+        assert_eq!(55, res.rounds[1].p2_budget);
+        // p2 copies p1's last allocation, rescaled to its own budget
+        assert_eq!(core::rescale(&p1, 55), res.rounds[1].p2);
+        assert!(res
+            .rounds
+            .iter()
+            .all(|r| r.p1.iter().sum::<i16>() == r.p1_budget
+                && r.p2.iter().sum::<i16>() == r.p2_budget));
+
+        // Reinforcements that take away more than survived leave a budget of 0
+        let config = MatchConfig {
+            carryover: Carryover::Attrition {
+                reinforcements: -60,
+            },
+            ..config
+        };
+        let res = play_match(&mut { p1 }, &mut CopyLast { opening: p2 }, &config);
+        assert_eq!(0, res.rounds[1].p2_budget);
+        assert_eq!([0; 10], res.rounds[1].p2);
+    }
+}
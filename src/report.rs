@@ -0,0 +1,274 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::core::{self, N_CASTLES};
+use crate::fixed_point::Precision;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    P1,
+    P2,
+    Tie,
+}
+
+/// How a single castle was fought over
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CastleResult {
+    pub p1_troops: i16,
+    pub p2_troops: i16,
+    pub winner: Side,
+    /// `p1_troops - p2_troops`
+    pub margin: i16,
+    pub p1_points: f32,
+    pub p2_points: f32,
+}
+
+/// Moving `troops` troops from castle index `from` to castle index `to` changes the
+/// result for `player`, giving the new scores
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Flip {
+    pub player: Side,
+    pub troops: i16,
+    pub from: usize,
+    pub to: usize,
+    pub p1_score: f32,
+    pub p2_score: f32,
+}
+
+/// A castle by castle breakdown of `core::battle`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BattleReport {
+    pub p1: [i16; N_CASTLES],
+    pub p2: [i16; N_CASTLES],
+    pub castles: [CastleResult; N_CASTLES],
+    pub p1_score: f32,
+    pub p2_score: f32,
+    pub winner: Side,
+    /// The smallest single troop transfer that would let the losing player win, or let
+    /// either player win a tie. None if no single transfer does it.
+    pub flip: Option<Flip>,
+}
+
+fn winner(p1_score: f32, p2_score: f32) -> Side {
+    match p1_score.partial_cmp(&p2_score) {
+        Some(Ordering::Greater) => Side::P1,
+        Some(Ordering::Less) => Side::P2,
+        _ => Side::Tie,
+    }
+}
+
+fn castle_result(castle_num: usize, p1_troops: i16, p2_troops: i16) -> CastleResult {
+    let value = (castle_num + 1) as f32;
+    let (winner, p1_points, p2_points) = match p1_troops.cmp(&p2_troops) {
+        Ordering::Greater => (Side::P1, value, 0.0),
+        Ordering::Less => (Side::P2, 0.0, value),
+        Ordering::Equal => (Side::Tie, value / 2.0, value / 2.0),
+    };
+    CastleResult {
+        p1_troops,
+        p2_troops,
+        winner,
+        margin: p1_troops - p2_troops,
+        p1_points,
+        p2_points,
+    }
+}
+
+/// find_winning_transfer looks for the fewest troops `player` has to move from one castle
+/// to another to beat `opponent`. Of the transfers that move that many troops, the one
+/// with the biggest winning margin is returned. Troops are counted in whatever units the
+/// allocations are in, so this works for fractions of a troop too.
+fn find_winning_transfer(
+    player: [i16; N_CASTLES],
+    opponent: [i16; N_CASTLES],
+) -> Option<(i16, usize, usize, f32, f32)> {
+    let most = player.iter().copied().max().unwrap_or(0);
+    (1..=most).find_map(|troops| {
+        (0..N_CASTLES)
+            .filter(|&from| player[from] >= troops)
+            .flat_map(|from| {
+                (0..N_CASTLES)
+                    .filter(move |&to| to != from)
+                    .map(move |to| (from, to))
+            })
+            .filter_map(|(from, to)| {
+                let mut moved = player;
+                moved[from] -= troops;
+                moved[to] += troops;
+                let (score, opponent_score) = core::battle(moved, opponent);
+                (score > opponent_score).then_some((troops, from, to, score, opponent_score))
+            })
+            .max_by(|a, b| (a.3 - a.4).total_cmp(&(b.3 - b.4)))
+    })
+}
+
+/// flip finds the smallest transfer that changes the result. When the battle was a tie,
+/// both players are tried, and the one that needs to move fewer troops is returned.
+fn flip(p1: [i16; N_CASTLES], p2: [i16; N_CASTLES], winner: Side) -> Option<Flip> {
+    let p1_flip = || {
+        find_winning_transfer(p1, p2).map(|(troops, from, to, p1_score, p2_score)| Flip {
+            player: Side::P1,
+            troops,
+            from,
+            to,
+            p1_score,
+            p2_score,
+        })
+    };
+    let p2_flip = || {
+        find_winning_transfer(p2, p1).map(|(troops, from, to, p2_score, p1_score)| Flip {
+            player: Side::P2,
+            troops,
+            from,
+            to,
+            p1_score,
+            p2_score,
+        })
+    };
+    match winner {
+        Side::P1 => p2_flip(),
+        Side::P2 => p1_flip(),
+        Side::Tie => [p1_flip(), p2_flip()]
+            .into_iter()
+            .flatten()
+            .min_by_key(|f| f.troops),
+    }
+}
+
+/// explain battles `p1` against `p2` like `core::battle`, but keeps the result of every
+/// castle, and works out the smallest change that would flip the result
+pub fn explain(p1: [i16; N_CASTLES], p2: [i16; N_CASTLES]) -> BattleReport {
+    let castles: [CastleResult; N_CASTLES] =
+        std::array::from_fn(|castle_num| castle_result(castle_num, p1[castle_num], p2[castle_num]));
+    let p1_score = castles.iter().map(|c| c.p1_points).sum();
+    let p2_score = castles.iter().map(|c| c.p2_points).sum();
+    let winner = winner(p1_score, p2_score);
+    BattleReport {
+        p1,
+        p2,
+        castles,
+        p1_score,
+        p2_score,
+        winner,
+        flip: flip(p1, p2, winner),
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::P1 => write!(f, "P1"),
+            Side::P2 => write!(f, "P2"),
+            Side::Tie => write!(f, "tie"),
+        }
+    }
+}
+
+impl BattleReport {
+    /// format writes the report with troop counts in decimals of `precision`. The
+    /// `Display` output is the same, in whole troops.
+    pub fn format(&self, precision: Precision) -> String {
+        let troops = |units: i16| precision.format(units);
+        let mut out = format!(
+            "P1: {}\nP2: {}\n\n",
+            precision.format_distribution(&self.p1),
+            precision.format_distribution(&self.p2)
+        );
+        out += &format!(
+            "{:>6} {:>5} {:>5} {:>5} {:>7} {:>7} {:>7}\n",
+            "castle", "P1", "P2", "won", "margin", "P1 pts", "P2 pts"
+        );
+        for (castle_num, c) in self.castles.iter().enumerate() {
+            let sign = if c.margin >= 0 { "+" } else { "" };
+            out += &format!(
+                "{:>6} {:>5} {:>5} {:>5} {:>7} {:>7.1} {:>7.1}\n",
+                castle_num + 1,
+                troops(c.p1_troops),
+                troops(c.p2_troops),
+                c.winner.to_string(),
+                format!("{sign}{}", troops(c.margin)),
+                c.p1_points,
+                c.p2_points
+            );
+        }
+        out += "\n";
+        match self.winner {
+            Side::Tie => out += &format!("Tie, {} to {}\n", self.p1_score, self.p2_score),
+            side => out += &format!("{side} wins, {} to {}\n", self.p1_score, self.p2_score),
+        }
+        match self.flip {
+            Some(flip) => {
+                out += &format!(
+                    "{} could move {} troop{} from castle {} to castle {} to win, {} to {}",
+                    flip.player,
+                    troops(flip.troops),
+                    if flip.troops == precision.scale() {
+                        ""
+                    } else {
+                        "s"
+                    },
+                    flip.from + 1,
+                    flip.to + 1,
+                    flip.p1_score,
+                    flip.p2_score
+                )
+            }
+            None => out += "No single troop transfer changes the result",
+        }
+        out
+    }
+}
+
+impl fmt::Display for BattleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(Precision::WHOLE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain_matches_battle() {
+        for _ in 0..100 {
+            let p1 = core::generate_uniform_random_distribution();
+            let p2 = core::generate_uniform_random_distribution();
+            let report = explain(p1, p2);
+            assert_eq!(core::battle(p1, p2), (report.p1_score, report.p2_score));
+            assert_eq!(55.0, report.p1_score + report.p2_score);
+            if let Some(flip) = report.flip {
+                assert_ne!(report.winner, flip.player);
+                assert_eq!(flip.player, winner(flip.p1_score, flip.p2_score));
+            }
+        }
+    }
+
+    #[test]
+    fn test_smallest_flip() {
+        let p1: [i16; 10] = [100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let p2: [i16; 10] = [0, 100, 0, 0, 0, 0, 0, 0, 0, 0];
+        let report = explain(p1, p2);
+        assert_eq!(Side::P2, report.winner);
+        assert_eq!(Side::Tie, report.castles[9].winner);
+        assert_eq!(100, report.castles[0].margin);
+
+        // The best single troop for P1 to move goes to the castle worth the most
+        let flip = report.flip.unwrap();
+        assert_eq!(
+            (Side::P1, 1, 0, 9),
+            (flip.player, flip.troops, flip.from, flip.to)
+        );
+        assert_eq!((32.0, 23.0), (flip.p1_score, flip.p2_score));
+
+        // In a tie, either player can move a troop to castle 9 to win
+        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p2: [i16; 10] = [0, 0, 0, 0, 10, 10, 10, 10, 10, 50];
+        let report = explain(p1, p2);
+        assert_eq!(Side::Tie, report.winner);
+        let flip = report.flip.unwrap();
+        assert_eq!((1, 8), (flip.troops, flip.to));
+    }
+}
@@ -0,0 +1,171 @@
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fmt;
+
+use crate::core::{self, BattleScore, N_CASTLES};
+use crate::fixed_point::Precision;
+
+/// How a candidate's results against a field change when `troops` troops are moved from
+/// one castle to another
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sensitivity {
+    pub candidate: [i16; N_CASTLES],
+    pub troops: i16,
+    /// How the candidate does against the field as it is
+    pub base: BattleScore,
+    /// `scores[from][to]` is how the candidate does after moving the troops from castle
+    /// index `from` to castle index `to`. None when `from == to`, or when castle `from`
+    /// has too few troops to move.
+    pub scores: [[Option<BattleScore>; N_CASTLES]; N_CASTLES],
+}
+
+impl Sensitivity {
+    /// delta is how many more wins the candidate gets after the move from `from` to `to`
+    pub fn delta(&self, from: usize, to: usize) -> Option<i64> {
+        self.scores[from][to].map(|s| s.wins as i64 - self.base.wins as i64)
+    }
+
+    fn deltas(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..N_CASTLES)
+            .flat_map(move |from| (0..N_CASTLES).filter_map(move |to| self.delta(from, to)))
+    }
+
+    /// The fraction of moves that cost the candidate at least one win. Close to 1 means the
+    /// candidate sits on a sharp peak, and close to 0 means it sits on a plateau.
+    pub fn fraction_worse(&self) -> f64 {
+        let (worse, total) = self.deltas().fold((0, 0), |(worse, total), d| {
+            (worse + (d < 0) as usize, total + 1)
+        });
+        if total == 0 {
+            return 0.0;
+        }
+        worse as f64 / total as f64
+    }
+
+    /// The biggest loss and gain in wins over all the moves
+    pub fn range(&self) -> (i64, i64) {
+        self.deltas()
+            .fold((0, 0), |(low, high), d| (low.min(d), high.max(d)))
+    }
+
+    /// heatmap lays out the change in wins for every move, with a row for each castle the
+    /// troops come from, and a column for each castle they go to. With `color`, cells are
+    /// shaded with ANSI colors, red for lost wins and green for gained wins. The number of
+    /// troops moved is shown in decimals of `precision`.
+    pub fn heatmap(&self, color: bool, precision: Precision) -> String {
+        let (low, high) = self.range();
+        let mut out = format!(
+            "Moving {} troop{}: {} wins as is, {:.0}% of moves lose wins, from {low:+} to {high:+}\n",
+            precision.format(self.troops),
+            if self.troops == precision.scale() {
+                ""
+            } else {
+                "s"
+            },
+            self.base.wins,
+            100.0 * self.fraction_worse()
+        );
+        out += "from\\to";
+        for to in 0..N_CASTLES {
+            out += &format!("{:>6}", to + 1);
+        }
+        out += "\n";
+        for from in 0..N_CASTLES {
+            out += &format!("{:>7}", from + 1);
+            for to in 0..N_CASTLES {
+                let delta = self.delta(from, to);
+                let cell = match delta {
+                    None => format!("{:>6}", "."),
+                    Some(d) => format!("{d:>+6}"),
+                };
+                out += &match delta {
+                    Some(d) if color && d != 0 => {
+                        let extreme = if d < 0 { low } else { high };
+                        // Bold for the moves in the top half of the range
+                        let weight = if 2 * d.abs() > extreme.abs() {
+                            "1;"
+                        } else {
+                            ""
+                        };
+                        let hue = if d < 0 { 31 } else { 32 };
+                        format!("\x1b[{weight}{hue}m{cell}\x1b[0m")
+                    }
+                    _ => cell,
+                };
+            }
+            out += "\n";
+        }
+        out
+    }
+}
+
+impl fmt::Display for Sensitivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.heatmap(false, Precision::WHOLE))
+    }
+}
+
+/// sensitivity scores `candidate` against `field` after every possible move of `troops`
+/// troops from one castle to another, in parallel
+pub fn sensitivity(
+    candidate: [i16; N_CASTLES],
+    field: &[[i16; N_CASTLES]],
+    troops: i16,
+) -> Sensitivity {
+    let _span = tracing::info_span!("sensitivity", troops).entered();
+    let moves: Vec<(usize, usize)> = (0..N_CASTLES)
+        .flat_map(|from| (0..N_CASTLES).map(move |to| (from, to)))
+        .filter(|&(from, to)| from != to && candidate[from] >= troops)
+        .collect();
+    let results: Vec<(usize, usize, BattleScore)> = moves
+        .into_par_iter()
+        .map(|(from, to)| {
+            let mut moved = candidate;
+            moved[from] -= troops;
+            moved[to] += troops;
+            (from, to, core::score_against_field(moved, field))
+        })
+        .collect();
+
+    let mut scores = [[None; N_CASTLES]; N_CASTLES];
+    for (from, to, score) in results {
+        scores[from][to] = Some(score);
+    }
+    Sensitivity {
+        candidate,
+        troops,
+        base: core::score_against_field(candidate, field),
+        scores,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitivity() {
+        let candidate: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let field: Vec<[i16; 10]> = (0..50)
+            .map(|_| core::generate_uniform_random_distribution())
+            .collect();
+        let s = sensitivity(candidate, &field, 1);
+        assert_eq!(90, s.scores.iter().flatten().flatten().count());
+        assert!((0..10).all(|c| s.delta(c, c).is_none()));
+
+        // Every move matches scoring the moved candidate directly
+        let mut moved = candidate;
+        moved[2] -= 1;
+        moved[7] += 1;
+        assert_eq!(
+            Some(core::score_against_field(moved, &field)),
+            s.scores[2][7]
+        );
+
+        // Castles with too few troops are skipped
+        let candidate: [i16; 10] = [1, 0, 0, 0, 0, 0, 0, 0, 0, 99];
+        let s = sensitivity(candidate, &field, 2);
+        assert_eq!(9, s.scores.iter().flatten().flatten().count());
+        assert_eq!(12, s.heatmap(true, Precision::WHOLE).lines().count());
+    }
+}
@@ -0,0 +1,459 @@
+//! A small local HTTP API over the engine, so other tools can share it instead of
+//! shelling out to the CLI. Every request and response body is JSON.
+//!
+//! - `POST /battle` with `{"p1": [...], "p2": [...]}` scores two allocations
+//! - `POST /rank` with `{"field": [[...], ...]}` plays everyone in the field against each
+//!   other, and ranks them from best to worst
+//! - `POST /fields` with `{"field": [[...], ...]}` stores a field, and returns its `id`
+//! - `GET /fields/{id}` returns a stored field
+//! - `POST /fields/{id}/best-response` with `{"restarts": 8, "seed": 1}` (both optional)
+//!   searches for the allocation that does best against a stored field. At most
+//!   `MAX_RESTARTS` restarts are allowed.
+//! - `POST /tournaments` with `{"players": [[...], ...]}` or `{"field_id": id}` starts
+//!   `final_battle::tournament` in the background, and returns the job's `id`. The
+//!   players must include at least 2 different allocations.
+//! - `GET /tournaments/{id}` gives the job's status, and its ranking once it has finished.
+//!   Only the last `MAX_FINISHED_JOBS` finished jobs are kept.
+
+use std::io;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::best_response;
+use crate::core::{self, BattleScore};
+use crate::final_battle;
+
+/// How many hill climbing restarts a best response uses if the request doesn't say
+const DEFAULT_RESTARTS: usize = 8;
+
+/// The most hill climbing restarts one best response request can ask for
+pub const MAX_RESTARTS: usize = 256;
+
+/// How many finished tournament jobs are remembered before the oldest are forgotten
+pub const MAX_FINISHED_JOBS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    /// The players from first place to last place
+    Finished {
+        ranking: Vec<[i16; 10]>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Everything the server remembers between requests
+#[derive(Debug, Default)]
+pub struct AppState {
+    next_id: AtomicU64,
+    fields: Mutex<FxHashMap<u64, Vec<[i16; 10]>>>,
+    jobs: Mutex<FxHashMap<u64, JobStatus>>,
+}
+
+impl AppState {
+    fn new_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn field(&self, id: u64) -> Result<Vec<[i16; 10]>, ApiError> {
+        self.fields
+            .lock()
+            .expect("Fields lock was poisoned")
+            .get(&id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound,
+}
+
+impl ApiError {
+    fn status(&self) -> u16 {
+        match self {
+            ApiError::BadRequest(_) => 400,
+            ApiError::NotFound => 404,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(msg) => msg.clone(),
+            ApiError::NotFound => "Not found".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BattleRequest {
+    p1: [i16; 10],
+    p2: [i16; 10],
+}
+
+#[derive(Debug, Serialize)]
+struct BattleResponse {
+    p1_score: f32,
+    p2_score: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldRequest {
+    field: Vec<[i16; 10]>,
+}
+
+#[derive(Debug, Serialize)]
+struct Ranked {
+    player: [i16; 10],
+    #[serde(flatten)]
+    score: BattleScore,
+}
+
+#[derive(Debug, Serialize)]
+struct RankResponse {
+    ranking: Vec<Ranked>,
+}
+
+#[derive(Debug, Serialize)]
+struct IdResponse {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TournamentRequest {
+    players: Option<Vec<[i16; 10]>>,
+    field_id: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BestResponseRequest {
+    restarts: Option<usize>,
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::BadRequest(format!("Invalid body: {e}")))
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Responses always serialize")
+}
+
+fn check_players(players: &[[i16; 10]], min_players: usize) -> Result<(), ApiError> {
+    if players.len() < min_players {
+        return Err(ApiError::BadRequest(format!(
+            "Need at least {min_players} players, got {}",
+            players.len()
+        )));
+    }
+    match players.iter().find(|p| !core::is_valid_distribution(p)) {
+        Some(p) => Err(ApiError::BadRequest(format!(
+            "{p:?} is not a valid allocation of {} troops",
+            core::N_TROOPS
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A tournament of copies of one player has nobody to eliminate
+fn check_distinct(players: &[[i16; 10]]) -> Result<(), ApiError> {
+    if players.iter().collect::<FxHashSet<_>>().len() < 2 {
+        return Err(ApiError::BadRequest(
+            "A tournament needs at least 2 different players".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Forgets the oldest finished jobs, so that at most `keep` of them are left. Ids only go
+/// up, so the smallest ids are the oldest.
+fn evict_finished_jobs(jobs: &mut FxHashMap<u64, JobStatus>, keep: usize) {
+    let mut finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, status)| **status != JobStatus::Running)
+        .map(|(&id, _)| id)
+        .collect();
+    if finished.len() <= keep {
+        return;
+    }
+    finished.sort_unstable();
+    for id in &finished[..finished.len() - keep] {
+        jobs.remove(id);
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64, ApiError> {
+    id.parse().map_err(|_| ApiError::NotFound)
+}
+
+fn start_tournament(state: &Arc<AppState>, players: Vec<[i16; 10]>) -> u64 {
+    let id = state.new_id();
+    state
+        .jobs
+        .lock()
+        .expect("Jobs lock was poisoned")
+        .insert(id, JobStatus::Running);
+
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        // Make sure that a panic turns into a failed job rather than one that never
+        // finishes
+        let res = panic::catch_unwind(|| final_battle::tournament(&players, false));
+        let status = match res {
+            Ok(res) => JobStatus::Finished {
+                ranking: res.into_iter().rev().collect(),
+            },
+            Err(_) => JobStatus::Failed {
+                error: "The tournament panicked".to_string(),
+            },
+        };
+        let mut jobs = state.jobs.lock().expect("Jobs lock was poisoned");
+        jobs.insert(id, status);
+        evict_finished_jobs(&mut jobs, MAX_FINISHED_JOBS);
+    });
+    id
+}
+
+/// handle routes one request, and returns the status code and JSON body to respond with
+pub fn handle(state: &Arc<AppState>, method: &Method, url: &str, body: &str) -> (u16, String) {
+    match route(state, method, url, body) {
+        Ok((status, body)) => (status, body),
+        Err(e) => (e.status(), to_json(&ErrorResponse { error: e.message() })),
+    }
+}
+
+fn route(
+    state: &Arc<AppState>,
+    method: &Method,
+    url: &str,
+    body: &str,
+) -> Result<(u16, String), ApiError> {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Post, ["battle"]) => {
+            let req: BattleRequest = parse(body)?;
+            check_players(&[req.p1, req.p2], 2)?;
+            let (p1_score, p2_score) = core::battle(req.p1, req.p2);
+            Ok((200, to_json(&BattleResponse { p1_score, p2_score })))
+        }
+        (Method::Post, ["rank"]) => {
+            let req: FieldRequest = parse(body)?;
+            check_players(&req.field, 2)?;
+            let ranking = core::run_battles_slice(&req.field, None)
+                .into_iter()
+                .rev()
+                .map(|(player, score)| Ranked { player, score })
+                .collect();
+            Ok((200, to_json(&RankResponse { ranking })))
+        }
+        (Method::Post, ["fields"]) => {
+            let req: FieldRequest = parse(body)?;
+            check_players(&req.field, 1)?;
+            let id = state.new_id();
+            state
+                .fields
+                .lock()
+                .expect("Fields lock was poisoned")
+                .insert(id, req.field);
+            Ok((201, to_json(&IdResponse { id })))
+        }
+        (Method::Get, ["fields", id]) => {
+            let field = state.field(parse_id(id)?)?;
+            Ok((200, to_json(&serde_json::json!({ "field": field }))))
+        }
+        (Method::Post, ["fields", id, "best-response"]) => {
+            let field = state.field(parse_id(id)?)?;
+            let req: BestResponseRequest = if body.trim().is_empty() {
+                BestResponseRequest::default()
+            } else {
+                parse(body)?
+            };
+            let restarts = req.restarts.unwrap_or(DEFAULT_RESTARTS);
+            if restarts > MAX_RESTARTS {
+                return Err(ApiError::BadRequest(format!(
+                    "At most {MAX_RESTARTS} restarts are allowed, got {restarts}"
+                )));
+            }
+            let (player, score) = best_response::best_response(
+                &field,
+                restarts,
+                req.seed.unwrap_or_else(rand::random),
+            );
+            Ok((200, to_json(&Ranked { player, score })))
+        }
+        (Method::Post, ["tournaments"]) => {
+            let req: TournamentRequest = parse(body)?;
+            let players = match (req.players, req.field_id) {
+                (Some(players), None) => players,
+                (None, Some(id)) => state.field(id)?,
+                _ => {
+                    return Err(ApiError::BadRequest(
+                        "Give exactly one of `players` or `field_id`".to_string(),
+                    ))
+                }
+            };
+            check_players(&players, 2)?;
+            check_distinct(&players)?;
+            let id = start_tournament(state, players);
+            Ok((202, to_json(&IdResponse { id })))
+        }
+        (Method::Get, ["tournaments", id]) => {
+            let id = parse_id(id)?;
+            let jobs = state.jobs.lock().expect("Jobs lock was poisoned");
+            let status = jobs.get(&id).ok_or(ApiError::NotFound)?;
+            Ok((200, to_json(status)))
+        }
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// serve listens on `addr` until the process is stopped. Each request gets its own
+/// thread, so a slow best response doesn't hold up anything else.
+pub fn serve(addr: &str) -> io::Result<()> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    println!("Listening on http://{}", server.server_addr());
+    let state = Arc::new(AppState::default());
+
+    for mut request in server.incoming_requests() {
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            let mut body = String::new();
+            let (status, json) = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => handle(&state, request.method(), request.url(), &body),
+                Err(e) => (
+                    400,
+                    to_json(&ErrorResponse {
+                        error: format!("Could not read body: {e}"),
+                    }),
+                ),
+            };
+            let header = Header::from_bytes("Content-Type", "application/json")
+                .expect("Content-Type header is valid");
+            let response = Response::from_string(json)
+                .with_status_code(status)
+                .with_header(header);
+            if let Err(e) = request.respond(response) {
+                eprintln!("Could not send response: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(body: &str) -> serde_json::Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn test_battle() {
+        let state = Arc::new(AppState::default());
+        let body =
+            r#"{"p1": [10,10,10,10,10,10,10,10,10,10], "p2": [0,10,10,10,10,10,10,10,10,20]}"#;
+        let (status, got) = handle(&state, &Method::Post, "/battle", body);
+        assert_eq!(200, status);
+        assert_eq!(
+            serde_json::json!({"p1_score": 23.0, "p2_score": 32.0}),
+            value(&got)
+        );
+
+        // Allocations have to use exactly all of the troops
+        let body = r#"{"p1": [10,10,10,10,10,10,10,10,10,10], "p2": [0,0,0,0,0,0,0,0,0,1]}"#;
+        let (status, _) = handle(&state, &Method::Post, "/battle", body);
+        assert_eq!(400, status);
+    }
+
+    #[test]
+    fn test_tournament_job() {
+        let state = Arc::new(AppState::default());
+        let body = r#"{"field": [[10,10,10,10,10,10,10,10,10,10], [100,0,0,0,0,0,0,0,0,0], [90,0,0,0,0,0,0,0,0,10]]}"#;
+        let (status, got) = handle(&state, &Method::Post, "/fields", body);
+        assert_eq!(201, status);
+        let field_id = value(&got)["id"].as_u64().unwrap();
+
+        let body = format!(r#"{{"field_id": {field_id}}}"#);
+        let (status, got) = handle(&state, &Method::Post, "/tournaments", &body);
+        assert_eq!(202, status);
+        let job_id = value(&got)["id"].as_u64().unwrap();
+
+        let url = format!("/tournaments/{job_id}");
+        let got = loop {
+            let (status, got) = handle(&state, &Method::Get, &url, "");
+            assert_eq!(200, status);
+            let got = value(&got);
+            if got["status"] != "running" {
+                break got;
+            }
+            thread::sleep(std::time::Duration::from_millis(5));
+        };
+        assert_eq!("finished", got["status"]);
+        assert_eq!(
+            serde_json::json!([10, 10, 10, 10, 10, 10, 10, 10, 10, 10]),
+            got["ranking"][0]
+        );
+    }
+
+    #[test]
+    fn test_rejects_degenerate_requests() {
+        let state = Arc::new(AppState::default());
+        let body =
+            r#"{"players": [[10,10,10,10,10,10,10,10,10,10], [10,10,10,10,10,10,10,10,10,10]]}"#;
+        let (status, got) = handle(&state, &Method::Post, "/tournaments", body);
+        assert_eq!(400, status);
+        assert_eq!(
+            "A tournament needs at least 2 different players",
+            value(&got)["error"]
+        );
+
+        let body = r#"{"field": [[10,10,10,10,10,10,10,10,10,10]]}"#;
+        let (_, got) = handle(&state, &Method::Post, "/fields", body);
+        let url = format!("/fields/{}/best-response", value(&got)["id"]);
+        let body = format!(r#"{{"restarts": {}}}"#, MAX_RESTARTS + 1);
+        assert_eq!(400, handle(&state, &Method::Post, &url, &body).0);
+    }
+
+    #[test]
+    fn test_evict_finished_jobs() {
+        let finished = || JobStatus::Finished {
+            ranking: Vec::new(),
+        };
+        let mut jobs: FxHashMap<u64, JobStatus> = FxHashMap::default();
+        jobs.insert(0, finished());
+        jobs.insert(1, JobStatus::Running);
+        jobs.insert(2, finished());
+        jobs.insert(3, finished());
+
+        evict_finished_jobs(&mut jobs, 2);
+        let mut left: Vec<u64> = jobs.keys().copied().collect();
+        left.sort_unstable();
+        assert_eq!(vec![1, 2, 3], left);
+    }
+
+    #[test]
+    fn test_not_found() {
+        let state = Arc::new(AppState::default());
+        assert_eq!(404, handle(&state, &Method::Get, "/fields/7", "").0);
+        assert_eq!(404, handle(&state, &Method::Get, "/nothing", "").0);
+    }
+}
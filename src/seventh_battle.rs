@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
 use crate::core;
+use crate::metrics;
 
 const fn _num_bits<T>() -> usize {
     std::mem::size_of::<T>() * 8
@@ -58,9 +59,11 @@ fn _sort_according_to_inds<T: Copy>(items: &[T], inds: &[usize]) -> Vec<T> {
 
 /// Runs a single elimination tournament, assuming the items in `pool` are sorted in
 /// the correct order
-fn _run_tournament(pool: &[[i16; 10]]) -> Vec<[i16; 10]> {
+pub fn run_single_elimination(pool: &[[i16; 10]]) -> Vec<[i16; 10]> {
+    let _span = tracing::info_span!("bracket", n_players = pool.len()).entered();
     let mut p: Vec<[i16; 10]> = pool.to_owned();
     while p.len() > 1 {
+        metrics::add_battles(p.len() as u64 / 2);
         p = p
             .chunks(2)
             .map(|p| core::battle(p[0], p[1]))
@@ -74,6 +77,140 @@ fn _run_tournament(pool: &[[i16; 10]]) -> Vec<[i16; 10]> {
     p
 }
 
+/// Which part of a double elimination tournament a match was played in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bracket {
+    Winners,
+    Losers,
+    GrandFinal,
+}
+
+/// A single match in a double elimination tournament
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BracketMatch {
+    pub bracket: Bracket,
+    /// Round number within the bracket, starting at 1
+    pub round: usize,
+    pub p1: [i16; 10],
+    pub p2: [i16; 10],
+    pub p1_score: f32,
+    pub p2_score: f32,
+    pub winner: [i16; 10],
+    pub loser: [i16; 10],
+}
+
+/// Play one match. Ties go to `p1`, the same as in `run_single_elimination`
+fn _play_match(bracket: Bracket, round: usize, p1: [i16; 10], p2: [i16; 10]) -> BracketMatch {
+    let (p1_score, p2_score) = core::battle(p1, p2);
+    metrics::add_battles(1);
+    let (winner, loser) = match p1_score.total_cmp(&p2_score) {
+        Ordering::Less => (p2, p1),
+        Ordering::Equal | Ordering::Greater => (p1, p2),
+    };
+    BracketMatch {
+        bracket,
+        round,
+        p1,
+        p2,
+        p1_score,
+        p2_score,
+        winner,
+        loser,
+    }
+}
+
+/// Play every pair in `players` against each other, recording the matches in `history`.
+/// Returns the winners and the losers, in bracket order.
+fn _play_bracket_round(
+    bracket: Bracket,
+    round: usize,
+    players: &[[i16; 10]],
+    history: &mut Vec<BracketMatch>,
+) -> (Vec<[i16; 10]>, Vec<[i16; 10]>) {
+    let mut winners = Vec::with_capacity(players.len() / 2);
+    let mut losers = Vec::with_capacity(players.len() / 2);
+    for pair in players.chunks(2) {
+        let m = _play_match(bracket, round, pair[0], pair[1]);
+        winners.push(m.winner);
+        losers.push(m.loser);
+        history.push(m);
+    }
+    (winners, losers)
+}
+
+/// Runs a double elimination tournament, assuming the items in `pool` are sorted in the
+/// correct order, and that there is a power of 2 of them.
+///
+/// Everyone starts in the winners' bracket. Losing once drops you into the losers'
+/// bracket, and losing twice knocks you out. The losers' bracket alternates between
+/// rounds where its survivors play each other, and rounds where they play the players
+/// who just dropped down from the winners' bracket. The drop-ins are played in reverse
+/// order, so that players don't immediately meet someone they already played.
+///
+/// The two bracket champions meet in the grand final, and its winner is the champion.
+/// There is no bracket reset: battles are deterministic, and the final's ties go to the
+/// winners' bracket champion, so a second final between the same two allocations could
+/// only ever repeat the first one's result.
+///
+/// Returns every match in the order it was played. The winner of the last match is the
+/// tournament champion.
+pub fn run_double_elimination(pool: &[[i16; 10]]) -> Vec<BracketMatch> {
+    assert!(
+        pool.len() >= 2 && pool.len().is_power_of_two(),
+        "Double elimination needs a power of 2 players, got {}",
+        pool.len()
+    );
+    let _span = tracing::info_span!("double_elimination", n_players = pool.len()).entered();
+    let mut history: Vec<BracketMatch> = Vec::with_capacity(2 * pool.len());
+
+    // The first round of the losers' bracket is made up only of players who lost in the
+    // first round of the winners' bracket
+    let (mut winners, mut losers) = _play_bracket_round(Bracket::Winners, 1, pool, &mut history);
+    let mut wb_round = 1;
+    let mut lb_round = 0;
+    if losers.len() > 1 {
+        lb_round += 1;
+        losers = _play_bracket_round(Bracket::Losers, lb_round, &losers, &mut history).0;
+    }
+
+    while winners.len() > 1 {
+        wb_round += 1;
+        let (new_winners, mut dropped) =
+            _play_bracket_round(Bracket::Winners, wb_round, &winners, &mut history);
+        winners = new_winners;
+
+        // Losers' bracket survivors play the players who just dropped down
+        dropped.reverse();
+        let pairs: Vec<[i16; 10]> = losers
+            .iter()
+            .zip(dropped.iter())
+            .flat_map(|(&survivor, &drop_in)| [survivor, drop_in])
+            .collect();
+        lb_round += 1;
+        losers = _play_bracket_round(Bracket::Losers, lb_round, &pairs, &mut history).0;
+
+        // Then the survivors play each other
+        if losers.len() > 1 {
+            lb_round += 1;
+            losers = _play_bracket_round(Bracket::Losers, lb_round, &losers, &mut history).0;
+        }
+    }
+
+    history.push(_play_match(Bracket::GrandFinal, 1, winners[0], losers[0]));
+    history
+}
+
+/// Seeds `players` with `_seed_players`, and runs a double elimination tournament over
+/// them. `players` should be sorted worst to best, the same as what
+/// `core::run_battles_slice` returns, and there must be a power of 2 of them.
+///
+/// Returns every match in the order it was played.
+pub fn seeded_double_elimination(players: &[[i16; 10]]) -> Vec<BracketMatch> {
+    let seeds = _seed_players(players.len());
+    let pool = _sort_according_to_inds(players, &seeds);
+    run_double_elimination(&pool)
+}
+
 /// For this simulation, each generation's pool is made up of
 /// total = n_top_keep + (n_top_keep * n_children) + n_random + n_previous_tops
 /// where n_top_keep is how many are carried over from the previous generation
@@ -86,7 +223,7 @@ fn _run_tournament(pool: &[[i16; 10]]) -> Vec<[i16; 10]> {
 /// elimination tournament, and then report the top winners. Ideally also save the top
 /// winners to a sqlite database for easy recall later. Then generate children of the
 /// top performers, some random strategies, and pick out some previous winners (if any).
-pub fn _seventh_battle_for_riddler_nation(
+pub fn seventh_battle_for_riddler_nation(
     // n_generations: usize,
     // n_top_keep: usize,
     // n_children: usize,
@@ -110,28 +247,19 @@ pub fn _seventh_battle_for_riddler_nation(
     //     .choose_multiple(&mut rng, pool.len().min(100))
     //     .collect();
 
-    let all_against_all_time = std::time::Instant::now();
-    // Play them all against eachother, and get them back sorted worst to best
-    let pool: Vec<_> = core::_run_battles_slice(&pool, None)
+    // Play them all against eachother, and get them back sorted worst to best. The time
+    // taken is recorded by the `battles` span
+    let pool: Vec<_> = core::run_battles_slice(&pool, None)
         .iter()
         .map(|(player, _)| *player)
         .collect();
-    println!(
-        "Running all strategies against eachother took {:0.6} s",
-        all_against_all_time.elapsed().as_secs_f64()
-    );
 
     // Now re-sort them according to the seed order
     let pool = _sort_according_to_inds(&pool, &seeds);
 
-    let tournament_time = std::time::Instant::now();
-    // Run the tournament, which returns the top performers
-    let new_top_performers: Vec<[i16; 10]> = _run_tournament(&pool);
-    println!(
-        "Running tournament took {:0.6} us",
-        tournament_time.elapsed().as_micros()
-    );
-    new_top_performers
+    // Run the tournament, which returns the top performers. The time taken is recorded
+    // by the `bracket` span
+    run_single_elimination(&pool)
 
     // Generate children, and random strategies
     // let new_kids: Vec<_> = new_top_performers
@@ -160,9 +288,9 @@ pub fn _seventh_battle_for_riddler_nation(
 
 #[cfg(test)]
 mod tests {
-    extern crate test;
     use super::*;
-    use test::Bencher;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_log_2() {
@@ -183,16 +311,6 @@ mod tests {
         assert_eq!(want, got);
     }
 
-    #[bench]
-    fn bench_seed_1024(b: &mut Bencher) {
-        b.iter(|| _seed_players(1024))
-    }
-
-    #[bench]
-    fn bench_seed_16384(b: &mut Bencher) {
-        b.iter(|| _seed_players(16384))
-    }
-
     #[test]
     fn test_next_power_of_2_after() {
         let inputs = vec![(1, 2), (2, 2), (3, 4), (9, 16), (240, 256), (900, 1024)];
@@ -201,16 +319,6 @@ mod tests {
         }
     }
 
-    #[bench]
-    fn bench_next_power_of_2_after_240(b: &mut Bencher) {
-        b.iter(|| _next_power_of_2_after(240));
-    }
-
-    #[bench]
-    fn bench_next_power_of_2_after_900(b: &mut Bencher) {
-        b.iter(|| _next_power_of_2_after(900));
-    }
-
     #[test]
     fn test_sort_according_to_inds() {
         let v = vec![4, 3, 2, 1];
@@ -223,7 +331,7 @@ mod tests {
         let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
         let p2: [i16; 10] = [0, 10, 10, 10, 10, 10, 10, 10, 10, 20];
         let players = vec![p1, p2];
-        let got = _run_tournament(&players);
+        let got = run_single_elimination(&players);
         let want = vec![p2];
         assert_eq!(want, got);
     }
@@ -235,8 +343,80 @@ mod tests {
         let p3: [i16; 10] = [19, 18, 17, 16, 15, 5, 4, 3, 2, 1];
         let p4: [i16; 10] = [20, 19, 18, 17, 16, 4, 3, 2, 1, 0];
         let players = vec![p1, p4, p3, p2];
-        let got = _run_tournament(&players);
+        let got = run_single_elimination(&players);
         let want = vec![p1];
         assert_eq!(want, got);
     }
+
+    #[test]
+    fn test_double_elimination_2() {
+        let p1: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p2: [i16; 10] = [0, 10, 10, 10, 10, 10, 10, 10, 10, 20];
+        let got = run_double_elimination(&[p1, p2]);
+        let brackets: Vec<Bracket> = got.iter().map(|m| m.bracket).collect();
+        assert_eq!(vec![Bracket::Winners, Bracket::GrandFinal], brackets);
+        assert_eq!(p2, got.last().unwrap().winner);
+    }
+
+    #[test]
+    fn test_double_elimination_4() {
+        let p1: [i16; 10] = [0, 10, 10, 10, 10, 10, 10, 10, 10, 20];
+        let p2: [i16; 10] = [10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let p3: [i16; 10] = [19, 18, 17, 16, 15, 5, 4, 3, 2, 1];
+        let p4: [i16; 10] = [20, 19, 18, 17, 16, 4, 3, 2, 1, 0];
+        let got = run_double_elimination(&[p1, p4, p3, p2]);
+        // 2 + 1 in the winners' bracket, 1 + 1 in the losers', and the grand final
+        assert_eq!(6, got.len());
+        assert_eq!(p1, got.last().unwrap().winner);
+    }
+
+    #[test]
+    fn test_double_elimination_losses() {
+        // Nobody can be knocked out before losing twice, and the champion can lose at
+        // most once
+        let mut rng = StdRng::seed_from_u64(7);
+        let pool: Vec<[i16; 10]> = (0..16)
+            .map(|_| core::generate_uniform_random_distribution_with_rng(&mut rng))
+            .collect();
+        let got = seeded_double_elimination(&pool);
+        let champion = got.last().unwrap().winner;
+        for player in &pool {
+            let n_losses = got.iter().filter(|m| m.loser == *player).count();
+            if *player == champion {
+                assert!(n_losses <= 1);
+            } else {
+                assert!(n_losses <= 2);
+            }
+        }
+        // 15 matches in the winners' bracket, 14 in the losers', and the grand final
+        assert_eq!(30, got.len());
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod benches {
+    extern crate test;
+
+    use super::*;
+    use test::Bencher;
+
+    #[bench]
+    fn bench_seed_1024(b: &mut Bencher) {
+        b.iter(|| _seed_players(1024))
+    }
+
+    #[bench]
+    fn bench_seed_16384(b: &mut Bencher) {
+        b.iter(|| _seed_players(16384))
+    }
+
+    #[bench]
+    fn bench_next_power_of_2_after_240(b: &mut Bencher) {
+        b.iter(|| _next_power_of_2_after(240));
+    }
+
+    #[bench]
+    fn bench_next_power_of_2_after_900(b: &mut Bencher) {
+        b.iter(|| _next_power_of_2_after(900));
+    }
 }
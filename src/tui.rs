@@ -0,0 +1,334 @@
+//! An interactive terminal UI for exploring a field. It shows a leaderboard of the field
+//! playing itself, how a typed in candidate does against the field as it is typed, and
+//! a histogram of how many troops the field sends to each castle.
+//!
+//! Keys: type an allocation to try it, Tab / Shift+Tab to change the sort column and
+//! direction, Up / Down to scroll, Left / Right to pick the castle for the histogram, and
+//! Esc to quit.
+
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{BarChart, Block, Borders, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+
+use crate::best_response::victory_points;
+use crate::core::{self, BattleScore, N_CASTLES};
+use crate::field;
+
+/// Width of each histogram bin, in troops
+const BIN_WIDTH: i16 = 5;
+
+/// Everything from this many troops up goes into the last histogram bin
+const LAST_BIN: i16 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    VictoryPoints,
+    Wins,
+    Ties,
+    Losses,
+}
+
+impl SortColumn {
+    fn next(self) -> Self {
+        match self {
+            SortColumn::VictoryPoints => SortColumn::Wins,
+            SortColumn::Wins => SortColumn::Ties,
+            SortColumn::Ties => SortColumn::Losses,
+            SortColumn::Losses => SortColumn::VictoryPoints,
+        }
+    }
+
+    fn key(self, score: &BattleScore) -> u32 {
+        match self {
+            SortColumn::VictoryPoints => victory_points(score),
+            SortColumn::Wins => score.wins,
+            SortColumn::Ties => score.ties,
+            SortColumn::Losses => score.losses,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            SortColumn::VictoryPoints => "VP",
+            SortColumn::Wins => "W",
+            SortColumn::Ties => "T",
+            SortColumn::Losses => "L",
+        }
+    }
+}
+
+pub struct App {
+    field: Vec<[i16; N_CASTLES]>,
+    /// Every player in the field, with how it did against the rest of the field
+    rows: Vec<([i16; N_CASTLES], BattleScore)>,
+    sort: SortColumn,
+    descending: bool,
+    table_state: TableState,
+    input: String,
+    castle: usize,
+}
+
+impl App {
+    pub fn new(field: Vec<[i16; N_CASTLES]>) -> Self {
+        let rows = core::run_battles_slice(&field, None);
+        let mut app = App {
+            field,
+            rows,
+            sort: SortColumn::VictoryPoints,
+            descending: true,
+            table_state: TableState::default().with_selected(Some(0)),
+            input: String::new(),
+            castle: N_CASTLES - 1,
+        };
+        app.sort_rows();
+        app
+    }
+
+    fn sort_rows(&mut self) {
+        let sort = self.sort;
+        self.rows.sort_by_key(|(_, score)| sort.key(score));
+        if self.descending {
+            self.rows.reverse();
+        }
+    }
+
+    /// candidate parses what has been typed so far, and plays it against the field.
+    /// Returns None if nothing has been typed.
+    pub fn candidate(&self) -> Option<Result<([i16; N_CASTLES], BattleScore), String>> {
+        if self.input.trim().is_empty() {
+            return None;
+        }
+        Some(
+            field::parse_distribution(&self.input)
+                .map(|p| (p, core::score_against_field(p, &self.field))),
+        )
+    }
+
+    /// histogram counts how many players in the field send each number of troops to
+    /// `castle`, in bins of `BIN_WIDTH` troops
+    pub fn histogram(&self, castle: usize) -> Vec<(String, u64)> {
+        let n_bins = (LAST_BIN / BIN_WIDTH) as usize + 1;
+        let mut counts = vec![0_u64; n_bins];
+        for p in &self.field {
+            let bin = (p[castle].min(LAST_BIN) / BIN_WIDTH) as usize;
+            counts[bin] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(bin, count)| {
+                let low = bin as i16 * BIN_WIDTH;
+                let label = if low >= LAST_BIN {
+                    format!("{low}+")
+                } else {
+                    format!("{low}-{}", low + BIN_WIDTH - 1)
+                };
+                (label, count)
+            })
+            .collect()
+    }
+
+    /// handle_key updates the app for one key press. Returns true if the app should quit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Esc => return true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Tab => {
+                self.sort = self.sort.next();
+                self.sort_rows();
+            }
+            KeyCode::BackTab => {
+                self.descending = !self.descending;
+                self.sort_rows();
+            }
+            KeyCode::Up => self.table_state.select_previous(),
+            KeyCode::Down => self.table_state.select_next(),
+            KeyCode::Left => self.castle = self.castle.saturating_sub(1),
+            KeyCode::Right => self.castle = (self.castle + 1).min(N_CASTLES - 1),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [input_area, main_area, help_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [table_area, histogram_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(main_area);
+
+        let candidate = self.candidate();
+        let result_line = match &candidate {
+            None => Line::from("Type an allocation, e.g. 0,0,10,10,10,10,15,15,15,15"),
+            Some(Ok((_, score))) => Line::from(format!(
+                "vs {} players: {} wins, {} ties, {} losses",
+                self.field.len(),
+                score.wins,
+                score.ties,
+                score.losses
+            ))
+            .style(Style::default().fg(Color::Green)),
+            Some(Err(e)) => Line::from(e.as_str()).style(Style::default().fg(Color::Red)),
+        };
+        let input = Paragraph::new(vec![Line::from(self.input.as_str()), result_line])
+            .block(Block::default().borders(Borders::ALL).title("Candidate"));
+        frame.render_widget(input, input_area);
+
+        let header = Row::new(vec!["#", "Allocation", "W", "T", "L", "VP"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.rows.iter().enumerate().map(|(idx, (p, score))| {
+            Row::new(vec![
+                (idx + 1).to_string(),
+                format!("{p:?}"),
+                score.wins.to_string(),
+                score.ties.to_string(),
+                score.losses.to_string(),
+                format!("{:.1}", victory_points(score) as f32 / 2.0),
+            ])
+        });
+        let direction = if self.descending {
+            "high to low"
+        } else {
+            "low to high"
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(5),
+                Constraint::Min(44),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(7),
+            ],
+        )
+        .header(header)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Field leaderboard, by {} {}",
+            self.sort.title(),
+            direction
+        )));
+        frame.render_stateful_widget(table, table_area, &mut self.table_state);
+
+        let histogram = self.histogram(self.castle);
+        let data: Vec<(&str, u64)> = histogram.iter().map(|(l, c)| (l.as_str(), *c)).collect();
+        let mean = self
+            .field
+            .iter()
+            .map(|p| p[self.castle] as f64)
+            .sum::<f64>()
+            / self.field.len().max(1) as f64;
+        let mut title = format!(
+            "Castle {} (worth {}), mean {:.1} troops",
+            self.castle + 1,
+            self.castle + 1,
+            mean
+        );
+        if let Some(Ok((p, _))) = &candidate {
+            title += &format!(", candidate {}", p[self.castle]);
+        }
+        let chart = BarChart::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .direction(Direction::Horizontal)
+            .bar_width(1)
+            .bar_gap(0)
+            .data(&data);
+        frame.render_widget(chart, histogram_area);
+
+        let help = Paragraph::new(
+            "Tab: sort column  Shift+Tab: sort direction  Up/Down: scroll  Left/Right: castle  Esc: quit",
+        );
+        frame.render_widget(help, help_area);
+    }
+}
+
+/// run takes over the terminal and runs the UI until the user quits
+pub fn run(field: Vec<[i16; N_CASTLES]>) -> io::Result<()> {
+    let mut app = App::new(field);
+    let mut terminal = ratatui::init();
+    let res = (|| loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && app.handle_key(key) {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::restore();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_field() -> Vec<[i16; 10]> {
+        vec![
+            [10, 10, 10, 10, 10, 10, 10, 10, 10, 10],
+            [100, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [90, 0, 0, 0, 0, 0, 0, 0, 0, 10],
+        ]
+    }
+
+    fn type_str(app: &mut App, s: &str) {
+        for c in s.chars() {
+            app.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn test_leaderboard_sorting() {
+        let mut app = App::new(test_field());
+        assert_eq!([10, 10, 10, 10, 10, 10, 10, 10, 10, 10], app.rows[0].0);
+
+        // Sort by losses, from high to low, then low to high
+        for _ in 0..3 {
+            app.handle_key(KeyEvent::from(KeyCode::Tab));
+        }
+        assert_eq!(SortColumn::Losses, app.sort);
+        assert_eq!([100, 0, 0, 0, 0, 0, 0, 0, 0, 0], app.rows[0].0);
+        app.handle_key(KeyEvent::from(KeyCode::BackTab));
+        assert_eq!([10, 10, 10, 10, 10, 10, 10, 10, 10, 10], app.rows[0].0);
+    }
+
+    #[test]
+    fn test_candidate_updates_as_typed() {
+        let mut app = App::new(test_field());
+        assert!(app.candidate().is_none());
+
+        type_str(&mut app, "0,10,10,10,10,10,10,10,10,");
+        assert!(matches!(app.candidate(), Some(Err(_))));
+
+        type_str(&mut app, "20");
+        let (_, score) = app.candidate().unwrap().unwrap();
+        assert_eq!(3, score.wins);
+
+        app.handle_key(KeyEvent::from(KeyCode::Backspace));
+        assert!(matches!(app.candidate(), Some(Err(_))));
+        assert!(app.handle_key(KeyEvent::from(KeyCode::Esc)));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let app = App::new(test_field());
+        let got = app.histogram(0);
+        assert_eq!(11, got.len());
+        assert_eq!(("0-4".to_string(), 0), got[0]);
+        assert_eq!(("10-14".to_string(), 1), got[2]);
+        assert_eq!(("50+".to_string(), 2), got[10]);
+    }
+}